@@ -289,6 +289,7 @@ impl<'a, T> Iterator for Descendants<'a, T> {
 }
 
 
+/// An edge of a node, as yielded by `Traverse` and `ReverseTraverse`.
 #[derive(Debug, Clone)]
 pub enum NodeEdge<T> {
     /// Indicates that start of a node that has children.
@@ -349,6 +350,18 @@ macro_rules! traverse_iterator {
                 }
             }
         }
+
+        impl<'a, T> $name<'a, T> {
+            /// Skips the descendants of the node whose `Start` edge was just yielded,
+            /// so that its `End` edge is yielded next.
+            pub fn skip_children(&mut self) {
+                if let Some(NodeEdge::Start(child)) = self.next {
+                    if let Some(parent) = child.parent.get() {
+                        self.next = Some(NodeEdge::End(parent));
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -4,32 +4,59 @@ use nodes::{NodeValue, ListType, ListDelimType, NodeLink, AstNode};
 use nodes::TableAlignment;
 use parser::ComrakOptions;
 use scanners;
-use std;
 use std::cmp::max;
+use std::io;
 use std::io::Write;
+use wrap::{LineWriter, Syntax};
 
 /// Formats an AST as CommonMark, modified by the given options.
 pub fn format_document<'a>(root: &'a AstNode<'a>, options: &ComrakOptions) -> String {
+    let mut output = vec![];
+    // Writing to a `Vec` can't fail, and the output only ever splits the input's text at ASCII
+    // characters, so it is always valid UTF-8.
+    format_document_to(root, options, &mut output).expect("writing to a Vec failed");
+    String::from_utf8(output).expect("CommonMark output was not valid UTF-8")
+}
+
+/// Formats an AST as CommonMark, modified by the given options, writing the result to `output`.
+///
+/// Output is written as each top-level block is completed.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, format_commonmark_to, ComrakOptions};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "Hello, _world_.\n", &options);
+///
+/// let mut output = vec![];
+/// format_commonmark_to(root, &options, &mut output).unwrap();
+/// assert_eq!(String::from_utf8(output).unwrap(), "Hello, *world*.\n");
+/// # }
+/// ```
+pub fn format_document_to<'a>(
+    root: &'a AstNode<'a>,
+    options: &ComrakOptions,
+    output: &mut Write,
+) -> io::Result<()> {
     let mut f = CommonMarkFormatter::new(root, options);
-    f.format(root);
-    if !f.v.is_empty() && f.v[f.v.len() - 1] != b'\n' {
-        f.v.push(b'\n');
+    if f.format_node(root, true) {
+        for n in root.children() {
+            f.format(n);
+            f.w.flush_lines(output)?;
+        }
+        f.format_node(root, false);
     }
-    String::from_utf8(f.v).unwrap()
+    f.w.finish(output)
 }
 
 struct CommonMarkFormatter<'a, 'o> {
     node: &'a AstNode<'a>,
     options: &'o ComrakOptions,
-    v: Vec<u8>,
-    prefix: Vec<u8>,
-    column: usize,
-    need_cr: u8,
-    last_breakable: usize,
-    begin_line: bool,
-    begin_content: bool,
+    w: LineWriter,
     no_linebreaks: bool,
-    in_tight_list_item: bool,
     custom_escape: Option<fn(&'a AstNode<'a>, u8) -> bool>,
 }
 
@@ -42,12 +69,12 @@ enum Escaping {
 }
 
 impl<'a, 'o> Write for CommonMarkFormatter<'a, 'o> {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.output(buf, false, Escaping::Literal);
         Ok(buf.len())
     }
 
-    fn flush(&mut self) -> std::io::Result<()> {
+    fn flush(&mut self) -> io::Result<()> {
         Ok(())
     }
 }
@@ -57,145 +84,39 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
         CommonMarkFormatter {
             node: node,
             options: options,
-            v: vec![],
-            prefix: vec![],
-            column: 0,
-            need_cr: 0,
-            last_breakable: 0,
-            begin_line: true,
-            begin_content: true,
+            w: LineWriter::new(options.width, Syntax::Markdown),
             no_linebreaks: false,
-            in_tight_list_item: false,
             custom_escape: None,
         }
     }
 
     fn output(&mut self, buf: &[u8], wrap: bool, escaping: Escaping) {
         let wrap = wrap && !self.no_linebreaks;
+        let node = self.node;
+        let options = self.options;
+        let custom_escape = self.custom_escape;
 
-        if self.in_tight_list_item && self.need_cr > 1 {
-            self.need_cr = 1;
-        }
-
-        let mut k = self.v.len() as i32 - 1;
-        while self.need_cr > 0 {
-            if k < 0 || self.v[k as usize] == b'\n' {
-                k -= 1;
-            } else {
-                self.v.push(b'\n');
-                if self.need_cr > 1 {
-                    self.v.extend(&self.prefix);
-                }
-            }
-            self.column = 0;
-            self.begin_line = true;
-            self.begin_content = true;
-            self.need_cr -= 1;
-        }
-
-        let mut i = 0;
-        while i < buf.len() {
-            if self.begin_line {
-                self.v.extend(&self.prefix);
-                self.column = self.prefix.len();
+        self.w.output(buf, wrap, |w, rest| {
+            let c = rest[0];
+            if custom_escape.map_or(false, |f| f(node, c)) {
+                w.v.push(b'\\');
             }
 
-            if self.custom_escape.is_some() && self.custom_escape.unwrap()(self.node, buf[i]) {
-                self.v.push(b'\\');
-            }
-
-            let nextc = buf.get(i + 1);
-            if buf[i] == b' ' && wrap {
-                if !self.begin_line {
-                    let last_nonspace = self.v.len();
-                    self.v.push(b' ');
-                    self.column += 1;
-                    self.begin_line = false;
-                    self.begin_content = false;
-                    while buf.get(i + 1) == Some(&(b' ')) {
-                        i += 1;
-                    }
-                    if !buf.get(i + 1).map_or(false, |&c| isdigit(c)) {
-                        self.last_breakable = last_nonspace;
-                    }
-                }
-            } else if buf[i] == b'\n' {
-                self.v.push(b'\n');
-                self.column = 0;
-                self.begin_line = true;
-                self.begin_content = true;
-                self.last_breakable = 0;
-            } else if escaping == Escaping::Literal {
-                self.v.push(buf[i]);
-                self.column += 1;
-                self.begin_line = false;
-                self.begin_content = self.begin_content && isdigit(buf[i]);
+            if escaping == Escaping::Literal {
+                w.push(c);
             } else {
-                self.outc(buf[i], escaping, nextc);
-                self.begin_line = false;
-                self.begin_content = self.begin_content && isdigit(buf[i]);
-            }
-
-            if self.options.width > 0 && self.column > self.options.width && !self.begin_line &&
-                self.last_breakable > 0
-            {
-                let remainder = self.v[self.last_breakable + 1..].to_vec();
-                self.v.truncate(self.last_breakable);
-                self.v.push(b'\n');
-                self.v.extend(&self.prefix);
-                self.v.extend(&remainder);
-                self.column = self.prefix.len() + remainder.len();
-                self.last_breakable = 0;
-                self.begin_line = false;
-                self.begin_content = false;
-            }
-
-            i += 1;
-        }
-    }
-
-    fn outc(&mut self, c: u8, escaping: Escaping, nextc: Option<&u8>) {
-        let follows_digit = !self.v.is_empty() && isdigit(self.v[self.v.len() - 1]);
-
-        let nextc = nextc.map_or(0, |&c| c);
-
-        let needs_escaping = c < 0x80 && escaping != Escaping::Literal &&
-            ((escaping == Escaping::Normal &&
-                  (c == b'*' || c == b'_' || c == b'[' || c == b']' || c == b'#' || c == b'<' ||
-                       c == b'>' ||
-                       c == b'\\' || c == b'`' || c == b'!' ||
-                       (c == b'&' && isalpha(nextc)) ||
-                       (c == b'!' && nextc == 0x5b) ||
-                       (self.begin_content && (c == b'-' || c == b'+' || c == b'=') &&
-                            !follows_digit) ||
-                       (self.begin_content && (c == b'.' || c == b')') && follows_digit &&
-                            (nextc == 0 || isspace(nextc))))) ||
-                 (escaping == Escaping::URL &&
-                      (c == b'`' || c == b'<' || c == b'>' || isspace(c) || c == b'\\' ||
-                           c == b')' || c == b'(')) ||
-                 (escaping == Escaping::Title &&
-                      (c == b'`' || c == b'<' || c == b'>' || c == b'"' || c == b'\\')));
-
-        if needs_escaping {
-            if isspace(c) {
-                write!(self.v, "%{:2x}", c).unwrap();
-                self.column += 3;
-            } else {
-                write!(self.v, "\\{}", c as char).unwrap();
-                self.column += 2;
+                outc(w, options, c, escaping, rest.get(1));
             }
-        } else {
-            self.v.push(c);
-            self.column += 1;
-        }
+            1
+        });
     }
 
     fn cr(&mut self) {
-        self.need_cr = max(self.need_cr, 1);
+        self.w.cr();
     }
 
     fn blankline(&mut self) {
-        self.need_cr = max(self.need_cr, 2);
+        self.w.blankline();
     }
 
     fn format_children(&mut self, node: &'a AstNode<'a>) {
@@ -224,6 +145,10 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
             return false;
         }
 
+        if let NodeValue::DescriptionDetails(..) = tmp.data.borrow().value {
+            return nodes::in_tight_description_list(tmp);
+        }
+
         let parent = match tmp.parent() {
             Some(parent) => parent,
             None => return false,
@@ -235,6 +160,10 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
             }
         }
 
+        if let NodeValue::DescriptionDetails(..) = parent.data.borrow().value {
+            return nodes::in_tight_description_list(parent);
+        }
+
         false
     }
 
@@ -247,19 +176,24 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                  _ => false,
              } && node.previous_sibling().is_none() && entering)
         {
-            self.in_tight_list_item = self.get_in_tight_list_item(node);
+            self.w.in_tight_list_item = self.get_in_tight_list_item(node);
         }
 
         match node.data.borrow().value {
             NodeValue::Document => (),
+            NodeValue::FrontMatter(ref literal) => {
+                if entering {
+                    self.output(literal.as_bytes(), false, Escaping::Literal);
+                }
+            }
             NodeValue::BlockQuote => {
                 if entering {
                     write!(self, "> ").unwrap();
-                    self.begin_content = true;
-                    write!(self.prefix, "> ").unwrap();
+                    self.w.begin_content = true;
+                    write!(self.w.prefix, "> ").unwrap();
                 } else {
-                    let new_len = self.prefix.len() - 2;
-                    self.prefix.truncate(new_len);
+                    let new_len = self.w.prefix.len() - 2;
+                    self.w.prefix.truncate(new_len);
                     self.blankline();
                 }
             }
@@ -319,13 +253,13 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                     } else {
                         self.write_all(&listmarker).unwrap();
                     }
-                    self.begin_content = true;
+                    self.w.begin_content = true;
                     for _ in 0..marker_width {
-                        write!(self.prefix, " ").unwrap();
+                        write!(self.w.prefix, " ").unwrap();
                     }
                 } else {
-                    let new_len = self.prefix.len() - marker_width;
-                    self.prefix.truncate(new_len);
+                    let new_len = self.w.prefix.len() - marker_width;
+                    self.w.prefix.truncate(new_len);
                     self.cr();
                 }
             }
@@ -335,14 +269,15 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                         write!(self, "#").unwrap();
                     }
                     write!(self, " ").unwrap();
-                    self.begin_content = true;
+                    self.w.begin_content = true;
                     self.no_linebreaks = true;
                 } else {
                     self.no_linebreaks = false;
                     self.blankline();
                 }
             }
-            NodeValue::CodeBlock(ref ncb) => {
+            NodeValue::CodeBlock(ref ncb) |
+            NodeValue::MathBlock(ref ncb) => {
                 if entering {
                     let first_in_list_item = node.previous_sibling().is_none() &&
                         match node.parent() {
@@ -366,10 +301,10 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                         !first_in_list_item
                     {
                         write!(self, "    ").unwrap();
-                        write!(self.prefix, "    ").unwrap();
+                        write!(self.w.prefix, "    ").unwrap();
                         write!(self, "{}", ncb.literal).unwrap();
-                        let new_len = self.prefix.len() - 4;
-                        self.prefix.truncate(new_len);
+                        let new_len = self.w.prefix.len() - 4;
+                        self.w.prefix.truncate(new_len);
                     } else {
                         let numticks = max(3, longest_backtick_sequence(&ncb.literal) + 1);
                         for _ in 0..numticks {
@@ -452,6 +387,14 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                     self.write_all(literal.as_bytes()).unwrap();
                 }
             }
+            NodeValue::Math(ref nm) => {
+                if entering {
+                    let delim = if nm.display_math { "$$" } else { "$" };
+                    write!(self, "{}", delim).unwrap();
+                    self.output(nm.literal.as_bytes(), false, Escaping::Literal);
+                    write!(self, "{}", delim).unwrap();
+                }
+            }
             NodeValue::Strong => {
                 if entering {
                     write!(self, "**").unwrap();
@@ -479,11 +422,9 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                 self.write_all(&[emph_delim]).unwrap();
             }
             NodeValue::Strikethrough => {
-                if entering {
-                    write!(self, "~").unwrap();
-                } else {
-                    write!(self, "~").unwrap();
-                }
+                // A single tilde would be read back as subscript.
+                let delim = if self.options.ext_subscript { "~~" } else { "~" };
+                write!(self, "{}", delim).unwrap();
             }
             NodeValue::Superscript => {
                 if entering {
@@ -492,6 +433,9 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                     write!(self, "^").unwrap();
                 }
             }
+            NodeValue::Subscript => {
+                write!(self, "~").unwrap();
+            }
             NodeValue::Link(ref nl) => {
                 if is_autolink(node, nl) {
                     if entering {
@@ -531,6 +475,70 @@ impl<'a, 'o> CommonMarkFormatter<'a, 'o> {
                     write!(self, ")").unwrap();
                 }
             }
+            NodeValue::FootnoteDefinition(ref name) => {
+                if entering {
+                    // Only a paragraph can start on the marker's line; any other block would be
+                    // indented by the width of the marker rather than the prefix.
+                    write!(self, "[^{}]:", name).unwrap();
+                    match node.first_child().map(|n| n.data.borrow().value.clone()) {
+                        Some(NodeValue::Paragraph) => {
+                            write!(self, " ").unwrap();
+                            self.w.begin_content = true;
+                        }
+                        _ => self.cr(),
+                    }
+                    write!(self.w.prefix, "    ").unwrap();
+                } else {
+                    let new_len = self.w.prefix.len() - 4;
+                    self.w.prefix.truncate(new_len);
+                    self.blankline();
+                }
+            }
+            NodeValue::DescriptionList(..) => {
+                if !entering &&
+                    match node.next_sibling() {
+                        Some(next_sibling) => {
+                            match next_sibling.data.borrow().value {
+                                NodeValue::CodeBlock(..) |
+                                NodeValue::DescriptionList(..) => true,
+                                _ => false,
+                            }
+                        }
+                        _ => false,
+                    }
+                {
+                    self.cr();
+                    write!(self, "<!-- end list -->").unwrap();
+                    self.blankline();
+                }
+            }
+            NodeValue::DescriptionItem => {
+                if !entering {
+                    self.blankline();
+                }
+            }
+            NodeValue::DescriptionTerm => (),
+            NodeValue::DescriptionDetails(..) => {
+                if entering {
+                    write!(self, ": ").unwrap();
+                    self.w.begin_content = true;
+                    write!(self.w.prefix, "  ").unwrap();
+                } else {
+                    let new_len = self.w.prefix.len() - 2;
+                    self.w.prefix.truncate(new_len);
+                    self.cr();
+                }
+            }
+            NodeValue::FootnoteReference(ref name) => {
+                if entering {
+                    write!(self, "[^{}]", name).unwrap();
+                }
+            }
+            NodeValue::TaskItem(checked) => {
+                if entering {
+                    write!(self, "{}", if checked { "[x] " } else { "[ ] " }).unwrap();
+                }
+            }
             NodeValue::Table(..) => {
                 if entering {
                     self.custom_escape = Some(table_escape);
@@ -667,3 +675,47 @@ fn table_escape<'a>(node: &'a AstNode<'a>, c: u8) -> bool {
         _ => c == b'|',
     }
 }
+
+fn outc(
+    w: &mut LineWriter,
+    options: &ComrakOptions,
+    c: u8,
+    escaping: Escaping,
+    nextc: Option<&u8>,
+) {
+    let follows_digit = !w.v.is_empty() && isdigit(w.v[w.v.len() - 1]);
+
+    let nextc = nextc.map_or(0, |&c| c);
+
+    let needs_escaping = c < 0x80 && escaping != Escaping::Literal &&
+        ((escaping == Escaping::Normal &&
+              (c == b'*' || c == b'_' || c == b'[' || c == b']' || c == b'#' || c == b'<' ||
+                   c == b'>' ||
+                   c == b'\\' || c == b'`' || c == b'!' ||
+                   (c == b'&' && isalpha(nextc)) ||
+                   (c == b'!' && nextc == 0x5b) ||
+                   (w.begin_content && (c == b'-' || c == b'+' || c == b'=') &&
+                        !follows_digit) ||
+                   (w.begin_content && c == b':' && options.ext_description_lists) ||
+                   (c == b'$' && options.ext_math) ||
+                   (c == b'~' && options.ext_subscript) ||
+                   (w.begin_content && (c == b'.' || c == b')') && follows_digit &&
+                        (nextc == 0 || isspace(nextc))))) ||
+             (escaping == Escaping::URL &&
+                  (c == b'`' || c == b'<' || c == b'>' || isspace(c) || c == b'\\' ||
+                       c == b')' || c == b'(')) ||
+             (escaping == Escaping::Title &&
+                  (c == b'`' || c == b'<' || c == b'>' || c == b'"' || c == b'\\')));
+
+    if needs_escaping {
+        if isspace(c) {
+            write!(w.v, "%{:2x}", c).unwrap();
+            w.column += 3;
+        } else {
+            write!(w.v, "\\{}", c as char).unwrap();
+            w.column += 2;
+        }
+    } else {
+        w.push(c);
+    }
+}
@@ -0,0 +1,41 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// An error encountered while reading a document.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading the input failed.
+    Io(io::Error),
+
+    /// The input was not valid UTF-8.  `offset` is the position of the first invalid byte,
+    /// counted from the start of the input.
+    InvalidUtf8 {
+        /// The offset of the first invalid byte.
+        offset: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => err.fmt(f),
+            Error::InvalidUtf8 { offset } => write!(f, "invalid UTF-8 at byte {}", offset),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        match *self {
+            Error::Io(ref err) => Some(err),
+            Error::InvalidUtf8 { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -1,19 +1,544 @@
 use ctype::isspace;
-use nodes::{TableAlignment, NodeValue, ListType, AstNode};
+use nodes::{TableAlignment, NodeValue, ListType, AstNode, NodeList, NodeCodeBlock, NodeHtmlBlock,
+            NodeHeading, NodeLink, NodeDescriptionList, NodeDescriptionDetails, NodeMath,
+            in_tight_description_list};
 use parser::ComrakOptions;
+use regex::Regex;
+use scanners;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
 
 /// Formats an AST as HTML, modified by the given options.
 pub fn format_document<'a>(root: &'a AstNode<'a>, options: &ComrakOptions) -> String {
-    let mut f = HtmlFormatter::new(options);
-    f.format(root, false);
-    f.s
+    let mut output = Vec::with_capacity(1024);
+    format_document_to(root, options, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
 }
 
-struct HtmlFormatter<'o> {
-    s: String,
+/// Formats an AST as HTML, modified by the given options, writing the output to `output` as it is
+/// produced.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, format_html_to, ComrakOptions};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "Hello, *world*!\n", &options);
+///
+/// let mut output = vec![];
+/// format_html_to(root, &options, &mut output).unwrap();
+/// assert_eq!(String::from_utf8(output).unwrap(), "<p>Hello, <em>world</em>!</p>\n");
+/// # }
+/// ```
+pub fn format_document_to<'a>(
+    root: &'a AstNode<'a>,
+    options: &ComrakOptions,
+    output: &mut Write,
+) -> io::Result<()> {
+    HtmlFormatter::new(options, output).format_document(root)
+}
+
+/// A syntax highlighter for fenced code blocks in HTML output.
+pub trait SyntaxHighlighter {
+    /// Returns the complete HTML for a fenced code block, given its info string and contents,
+    /// which is output in place of the usual `<pre><code>` element.  Neither argument is escaped.
+    ///
+    /// If `None` is returned, the code block is rendered as normal.
+    fn highlight(&self, info: &str, code: &str) -> Option<String>;
+}
+
+struct WriteWithLast<'w> {
+    output: &'w mut Write,
+    last_was_lf: bool,
+}
+
+impl<'w> Write for WriteWithLast<'w> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.output.write(buf)?;
+        if n > 0 {
+            self.last_was_lf = buf[n - 1] == b'\n';
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Converts header text into unique anchor IDs, the way GitHub does.
+///
+/// The same `Anchorizer` should be used for every heading in a document, so that repeated
+/// headings are given distinct IDs.
+///
+/// ```
+/// # use comrak::Anchorizer;
+/// let mut anchorizer = Anchorizer::new();
+/// assert_eq!(anchorizer.anchorize("Hello, World!"), "hello-world");
+/// assert_eq!(anchorizer.anchorize("Hello, World!"), "hello-world-1");
+/// assert_eq!(anchorizer.anchorize("Ünicode `code` & more"), "ünicode-code--more");
+/// ```
+#[derive(Debug, Default)]
+pub struct Anchorizer(HashSet<String>);
+
+impl Anchorizer {
+    /// Construct a new anchorizer.
+    pub fn new() -> Self {
+        Anchorizer(HashSet::new())
+    }
+
+    /// Returns an anchor ID for the given header text which has not yet been returned by this
+    /// anchorizer.
+    pub fn anchorize(&mut self, header: &str) -> String {
+        lazy_static! {
+            static ref REJECTED_CHARS: Regex = Regex::new(r"[^\p{L}\p{M}\p{N}\p{Pc} -]").unwrap();
+        }
+
+        let id = REJECTED_CHARS
+            .replace_all(&header.to_lowercase(), "")
+            .replace(' ', "-");
+
+        let mut uniq = 0;
+        let mut anchor = id.clone();
+        while self.0.contains(&anchor) {
+            uniq += 1;
+            anchor = format!("{}-{}", id, uniq);
+        }
+
+        self.0.insert(anchor.clone());
+        anchor
+    }
+}
+
+/// Collects the text content of a node and its descendants, as used for heading anchors.
+pub fn collect_text<'a>(node: &'a AstNode<'a>, output: &mut String) {
+    match node.data.borrow().value {
+        NodeValue::Text(ref literal) |
+        NodeValue::Code(ref literal) => *output += literal,
+        NodeValue::Math(ref nm) => *output += &nm.literal,
+        NodeValue::LineBreak | NodeValue::SoftBreak => output.push(' '),
+        _ => {
+            for n in node.children() {
+                collect_text(n, output);
+            }
+        }
+    }
+}
+
+/// The state of an HTML formatter, as passed to each method of an `HtmlRenderer`.
+///
+/// Renderers write their output to the formatter through its `Write` implementation, using its
+/// escaping helpers as needed.  Its `render_*` methods render each kind of node the way
+/// `format_html` does.
+pub struct HtmlFormatter<'o> {
+    output: WriteWithLast<'o>,
     options: &'o ComrakOptions,
+    highlighter: Option<&'o SyntaxHighlighter>,
+    renderer: Option<&'o mut HtmlRenderer>,
+    footnote_ix: u32,
+    footnote_refs: HashMap<String, (u32, u32)>,
+    footnote_ref_count: u32,
+    task_ix: u32,
+    anchorizer: Anchorizer,
+}
+
+impl<'o> fmt::Debug for HtmlFormatter<'o> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HtmlFormatter")
+            .field("options", self.options)
+            .field("footnote_ix", &self.footnote_ix)
+            .finish()
+    }
+}
+
+impl<'o> Write for HtmlFormatter<'o> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Renders each kind of node as HTML.
+///
+/// Every method defaults to the `HtmlFormatter` method of the same name, which renders the node
+/// the way `format_html` does, so implementations need only override the kinds of node they
+/// want to render differently.  See `HtmlFormatter::with_renderer` for an example.
+///
+/// Each method is called with `entering` set before the node's children are rendered, and again
+/// with it unset afterwards.  When entering, it returns whether the children should be rendered
+/// as plain text, as for an image's alt text.
+pub trait HtmlRenderer {
+    /// Renders the document root.
+    fn render_document<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_document(node, entering)
+    }
+
+    /// Renders front matter.
+    fn render_front_matter<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        front_matter: &str,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_front_matter(node, front_matter, entering)
+    }
+
+    /// Renders a block quote.
+    fn render_block_quote<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_block_quote(node, entering)
+    }
+
+    /// Renders a list.
+    fn render_list<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        nl: &NodeList,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_list(node, nl, entering)
+    }
+
+    /// Renders a list item.
+    fn render_item<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        nl: &NodeList,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_item(node, nl, entering)
+    }
+
+    /// Renders a code block.
+    fn render_code_block<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        ncb: &NodeCodeBlock,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_code_block(node, ncb, entering)
+    }
+
+    /// Renders an HTML block.
+    fn render_html_block<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        nhb: &NodeHtmlBlock,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_html_block(node, nhb, entering)
+    }
+
+    /// Renders a paragraph.
+    fn render_paragraph<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_paragraph(node, entering)
+    }
+
+    /// Renders a heading.
+    fn render_heading<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        nch: &NodeHeading,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_heading(node, nch, entering)
+    }
+
+    /// Renders a thematic break.
+    fn render_thematic_break<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_thematic_break(node, entering)
+    }
+
+    /// Renders a table.
+    fn render_table<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        alignments: &[TableAlignment],
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_table(node, alignments, entering)
+    }
+
+    /// Renders a table row.
+    fn render_table_row<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        header: bool,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_table_row(node, header, entering)
+    }
+
+    /// Renders a table cell.
+    fn render_table_cell<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_table_cell(node, entering)
+    }
+
+    /// Renders a footnote definition.
+    fn render_footnote_definition<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        name: &str,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_footnote_definition(node, name, entering)
+    }
+
+    /// Renders a description list.
+    fn render_description_list<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        ndl: &NodeDescriptionList,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_description_list(node, ndl, entering)
+    }
+
+    /// Renders a description list item.
+    fn render_description_item<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_description_item(node, entering)
+    }
+
+    /// Renders a description term.
+    fn render_description_term<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_description_term(node, entering)
+    }
+
+    /// Renders description details.
+    fn render_description_details<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        ndd: &NodeDescriptionDetails,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_description_details(node, ndd, entering)
+    }
+
+    /// Renders a math block.
+    fn render_math_block<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        ncb: &NodeCodeBlock,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_math_block(node, ncb, entering)
+    }
+
+    /// Renders text.
+    fn render_text<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        literal: &str,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_text(node, literal, entering)
+    }
+
+    /// Renders a soft line break.
+    fn render_soft_break<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_soft_break(node, entering)
+    }
+
+    /// Renders a hard line break.
+    fn render_line_break<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_line_break(node, entering)
+    }
+
+    /// Renders an inline code span.
+    fn render_code<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        literal: &str,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_code(node, literal, entering)
+    }
+
+    /// Renders inline HTML.
+    fn render_html_inline<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        literal: &str,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_html_inline(node, literal, entering)
+    }
+
+    /// Renders emphasis.
+    fn render_emph<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_emph(node, entering)
+    }
+
+    /// Renders strong emphasis.
+    fn render_strong<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_strong(node, entering)
+    }
+
+    /// Renders strikethrough.
+    fn render_strikethrough<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_strikethrough(node, entering)
+    }
+
+    /// Renders superscript.
+    fn render_superscript<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_superscript(node, entering)
+    }
+
+    /// Renders subscript.
+    fn render_subscript<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_subscript(node, entering)
+    }
+
+    /// Renders a link.
+    fn render_link<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        nl: &NodeLink,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_link(node, nl, entering)
+    }
+
+    /// Renders an image.
+    fn render_image<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        nl: &NodeLink,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_image(node, nl, entering)
+    }
+
+    /// Renders a footnote reference.
+    fn render_footnote_reference<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        name: &str,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_footnote_reference(node, name, entering)
+    }
+
+    /// Renders a math span.
+    fn render_math<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        nm: &NodeMath,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_math(node, nm, entering)
+    }
+
+    /// Renders a task list item's checkbox.
+    fn render_task_item<'a>(
+        &mut self,
+        f: &mut HtmlFormatter,
+        node: &'a AstNode<'a>,
+        checked: bool,
+        entering: bool,
+    ) -> io::Result<bool> {
+        f.render_task_item(node, checked, entering)
+    }
 }
 
+struct DefaultRenderer;
+
+impl HtmlRenderer for DefaultRenderer {}
+
 fn tagfilter(literal: &str) -> bool {
     lazy_static! {
         static ref TAGFILTER_BLACKLIST: [&'static str; 9] =
@@ -42,7 +567,7 @@ fn tagfilter(literal: &str) -> bool {
     false
 }
 
-fn tagfilter_block(input: &str, mut o: &mut String) {
+fn tagfilter_block(input: &str, o: &mut Write) -> io::Result<()> {
     let src = input.as_bytes();
     let size = src.len();
     let mut i = 0;
@@ -54,7 +579,7 @@ fn tagfilter_block(input: &str, mut o: &mut String) {
         }
 
         if i > org {
-            *o += &input[org..i];
+            o.write_all(&src[org..i])?;
         }
 
         if i >= size {
@@ -62,31 +587,152 @@ fn tagfilter_block(input: &str, mut o: &mut String) {
         }
 
         if tagfilter(&input[i..]) {
-            *o += "&lt;";
+            o.write_all(b"&lt;")?;
         } else {
-            o.push('<');
+            o.write_all(b"<")?;
         }
 
         i += 1;
     }
+
+    Ok(())
+}
+
+fn footnote_ref_id(ix: u32, count: u32) -> String {
+    if count == 1 {
+        format!("fnref{}", ix)
+    } else {
+        format!("fnref{}-{}", ix, count)
+    }
 }
 
 impl<'o> HtmlFormatter<'o> {
-    fn new(options: &'o ComrakOptions) -> Self {
+    /// Creates a formatter writing to `output`, which renders the way `format_html_to` does until
+    /// given a renderer or highlighter.
+    pub fn new(options: &'o ComrakOptions, output: &'o mut Write) -> Self {
         HtmlFormatter {
-            s: String::with_capacity(1024),
+            output: WriteWithLast {
+                output: output,
+                last_was_lf: true,
+            },
             options: options,
+            highlighter: None,
+            renderer: None,
+            footnote_ix: 0,
+            footnote_refs: HashMap::new(),
+            footnote_ref_count: 0,
+            task_ix: 0,
+            anchorizer: Anchorizer::new(),
         }
     }
 
-    fn cr(&mut self) {
-        let l = self.s.len();
-        if l > 0 && self.s.as_bytes()[l - 1] != b'\n' {
-            self.s += "\n";
+    /// Uses `highlighter` to render fenced code blocks.
+    ///
+    /// ```
+    /// # extern crate comrak;
+    /// # extern crate typed_arena;
+    /// # use comrak::{parse_document, ComrakOptions, HtmlFormatter, SyntaxHighlighter};
+    /// struct Shouty;
+    ///
+    /// impl SyntaxHighlighter for Shouty {
+    ///     fn highlight(&self, info: &str, code: &str) -> Option<String> {
+    ///         if info == "shout" {
+    ///             Some(format!("<pre class=\"shout\">{}</pre>\n", code.to_uppercase()))
+    ///         } else {
+    ///             None
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// # fn main() {
+    /// let arena = typed_arena::Arena::new();
+    /// let options = ComrakOptions::default();
+    /// let root = parse_document(&arena, "```shout\nhi\n```\n\n```rust\nfn\n```\n", &options);
+    ///
+    /// let mut output = vec![];
+    /// HtmlFormatter::new(&options, &mut output)
+    ///     .with_highlighter(&Shouty)
+    ///     .format_document(root)
+    ///     .unwrap();
+    /// assert_eq!(String::from_utf8(output).unwrap(),
+    ///            "<pre class=\"shout\">HI\n</pre>\n\
+    ///             <pre><code class=\"language-rust\">fn\n</code></pre>\n");
+    /// # }
+    /// ```
+    pub fn with_highlighter(mut self, highlighter: &'o SyntaxHighlighter) -> Self {
+        self.highlighter = Some(highlighter);
+        self
+    }
+
+    /// Uses `renderer` to render each node.
+    ///
+    /// ```
+    /// # extern crate comrak;
+    /// # extern crate typed_arena;
+    /// # use comrak::{parse_document, ComrakOptions, HtmlFormatter, HtmlRenderer};
+    /// # use comrak::nodes::{AstNode, NodeLink};
+    /// # use std::io::{self, Write};
+    /// struct NoFollow;
+    ///
+    /// impl HtmlRenderer for NoFollow {
+    ///     fn render_link<'a>(
+    ///         &mut self,
+    ///         f: &mut HtmlFormatter,
+    ///         node: &'a AstNode<'a>,
+    ///         nl: &NodeLink,
+    ///         entering: bool,
+    ///     ) -> io::Result<bool> {
+    ///         if !entering {
+    ///             return f.render_link(node, nl, entering);
+    ///         }
+    ///         f.write_all(b"<a rel=\"nofollow\" href=\"")?;
+    ///         f.escape_href(&nl.url)?;
+    ///         f.write_all(b"\">")?;
+    ///         Ok(false)
+    ///     }
+    /// }
+    ///
+    /// # fn main() {
+    /// let arena = typed_arena::Arena::new();
+    /// let options = ComrakOptions::default();
+    /// let root = parse_document(&arena, "[Hi](https://example.com/) *there*\n", &options);
+    ///
+    /// let mut output = vec![];
+    /// HtmlFormatter::new(&options, &mut output)
+    ///     .with_renderer(&mut NoFollow)
+    ///     .format_document(root)
+    ///     .unwrap();
+    /// assert_eq!(String::from_utf8(output).unwrap(),
+    ///            "<p><a rel=\"nofollow\" href=\"https://example.com/\">Hi</a> \
+    ///             <em>there</em></p>\n");
+    /// # }
+    /// ```
+    pub fn with_renderer(mut self, renderer: &'o mut HtmlRenderer) -> Self {
+        self.renderer = Some(renderer);
+        self
+    }
+
+    /// Formats the document `root`, writing the output as it is produced.
+    pub fn format_document<'a>(mut self, root: &'a AstNode<'a>) -> io::Result<()> {
+        self.format(root, false)?;
+        self.flush()
+    }
+
+    /// The options the document is being formatted with.
+    pub fn options(&self) -> &'o ComrakOptions {
+        self.options
+    }
+
+    /// Outputs a newline, unless the output is already at the start of a line.
+    pub fn cr(&mut self) -> io::Result<()> {
+        if !self.output.last_was_lf {
+            self.output.write_all(b"\n")?;
         }
+        Ok(())
     }
 
-    fn escape(&mut self, buffer: &str) {
+    /// Outputs `buffer`, escaped for use as HTML text or in an attribute value.
+    pub fn escape(&mut self, buffer: &str) -> io::Result<()> {
         lazy_static! {
             static ref NEEDS_ESCAPED: [bool; 256] = {
                 let mut sc = [false; 256];
@@ -108,7 +754,7 @@ impl<'o> HtmlFormatter<'o> {
             }
 
             if i > org {
-                self.s += &buffer[org..i];
+                self.output.write_all(&src[org..i])?;
             }
 
             if i >= size {
@@ -116,18 +762,21 @@ impl<'o> HtmlFormatter<'o> {
             }
 
             match src[i] as char {
-                '"' => self.s += "&quot;",
-                '&' => self.s += "&amp;",
-                '<' => self.s += "&lt;",
-                '>' => self.s += "&gt;",
+                '"' => self.output.write_all(b"&quot;")?,
+                '&' => self.output.write_all(b"&amp;")?,
+                '<' => self.output.write_all(b"&lt;")?,
+                '>' => self.output.write_all(b"&gt;")?,
                 _ => unreachable!(),
             }
 
             i += 1;
         }
+
+        Ok(())
     }
 
-    fn escape_href(&mut self, buffer: &str) {
+    /// Outputs `buffer`, escaped for use as a URL in an `href` or `src` attribute.
+    pub fn escape_href(&mut self, buffer: &str) -> io::Result<()> {
         lazy_static! {
             static ref HREF_SAFE: [bool; 256] = {
                 let mut a = [false; 256];
@@ -149,7 +798,7 @@ impl<'o> HtmlFormatter<'o> {
             }
 
             if i > org {
-                self.s += &buffer[org..i];
+                self.output.write_all(&src[org..i])?;
             }
 
             if i >= size {
@@ -157,311 +806,852 @@ impl<'o> HtmlFormatter<'o> {
             }
 
             match src[i] as char {
-                '&' => self.s += "&amp;",
-                '\'' => self.s += "&#x27;",
-                _ => self.s += &format!("%{:02X}", src[i]),
+                '&' => self.output.write_all(b"&amp;")?,
+                '\'' => self.output.write_all(b"&#x27;")?,
+                _ => write!(self.output, "%{:02X}", src[i])?,
             }
 
             i += 1;
         }
+
+        Ok(())
     }
 
-    fn format_children<'a>(&mut self, node: &'a AstNode<'a>, plain: bool) {
+    /// Outputs a `data-sourcepos` attribute for `node`, if the `sourcepos` option is set.
+    pub fn render_sourcepos<'a>(&mut self, node: &'a AstNode<'a>) -> io::Result<()> {
+        if self.options.sourcepos {
+            let ast = node.data.borrow();
+            write!(
+                self.output,
+                " data-sourcepos=\"{}:{}-{}:{}\"",
+                ast.start_line,
+                ast.start_column,
+                ast.end_line,
+                ast.end_column
+            )?;
+        }
+        Ok(())
+    }
+
+    fn format_children<'a>(&mut self, node: &'a AstNode<'a>, plain: bool) -> io::Result<()> {
         for n in node.children() {
-            self.format(n, plain);
+            self.format(n, plain)?;
         }
+        Ok(())
     }
 
-    fn format<'a>(&mut self, node: &'a AstNode<'a>, plain: bool) {
+    fn format<'a>(&mut self, node: &'a AstNode<'a>, plain: bool) -> io::Result<()> {
         if plain {
             match node.data.borrow().value {
                 NodeValue::Text(ref literal) |
                 NodeValue::Code(ref literal) |
-                NodeValue::HtmlInline(ref literal) => self.escape(literal),
-                NodeValue::LineBreak | NodeValue::SoftBreak => self.s.push(' '),
+                NodeValue::HtmlInline(ref literal) => self.escape(literal)?,
+                NodeValue::Math(ref nm) => self.escape(&nm.literal)?,
+                NodeValue::LineBreak | NodeValue::SoftBreak => self.output.write_all(b" ")?,
                 _ => (),
             }
-            self.format_children(node, true);
+            self.format_children(node, true)?;
         } else {
-            let new_plain = self.format_node(node, true);
-            self.format_children(node, new_plain);
-            self.format_node(node, false);
-        }
-    }
-
-    fn format_node<'a>(&mut self, node: &'a AstNode<'a>, entering: bool) -> bool {
-        match node.data.borrow().value {
-            NodeValue::Document => (),
-            NodeValue::BlockQuote => {
-                if entering {
-                    self.cr();
-                    self.s += "<blockquote>\n";
-                } else {
-                    self.cr();
-                    self.s += "</blockquote>\n";
-                }
+            // The value is copied out so that renderers are free to modify the node.
+            let value = node.data.borrow().value.clone();
+            let new_plain = self.format_node(node, &value, true)?;
+            self.format_children(node, new_plain)?;
+            self.format_node(node, &value, false)?;
+        }
+        Ok(())
+    }
+
+    fn format_node<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        value: &NodeValue,
+        entering: bool,
+    ) -> io::Result<bool> {
+        let mut renderer = self.renderer.take();
+        let result = match renderer {
+            Some(ref mut r) => self.dispatch(&mut **r, node, value, entering),
+            None => self.dispatch(&mut DefaultRenderer, node, value, entering),
+        };
+        self.renderer = renderer;
+        result
+    }
+
+    fn dispatch<'a>(
+        &mut self,
+        r: &mut HtmlRenderer,
+        node: &'a AstNode<'a>,
+        value: &NodeValue,
+        entering: bool,
+    ) -> io::Result<bool> {
+        match *value {
+            NodeValue::Document => r.render_document(self, node, entering),
+            NodeValue::FrontMatter(ref fm) => r.render_front_matter(self, node, fm, entering),
+            NodeValue::BlockQuote => r.render_block_quote(self, node, entering),
+            NodeValue::List(ref nl) => r.render_list(self, node, nl, entering),
+            NodeValue::Item(ref nl) => r.render_item(self, node, nl, entering),
+            NodeValue::CodeBlock(ref ncb) => r.render_code_block(self, node, ncb, entering),
+            NodeValue::HtmlBlock(ref nhb) => r.render_html_block(self, node, nhb, entering),
+            NodeValue::Paragraph => r.render_paragraph(self, node, entering),
+            NodeValue::Heading(ref nch) => r.render_heading(self, node, nch, entering),
+            NodeValue::ThematicBreak => r.render_thematic_break(self, node, entering),
+            NodeValue::Table(ref aligns) => r.render_table(self, node, aligns, entering),
+            NodeValue::TableRow(header) => r.render_table_row(self, node, header, entering),
+            NodeValue::TableCell => r.render_table_cell(self, node, entering),
+            NodeValue::FootnoteDefinition(ref name) => {
+                r.render_footnote_definition(self, node, name, entering)
             }
-            NodeValue::List(ref nl) => {
-                if entering {
-                    self.cr();
-                    if nl.list_type == ListType::Bullet {
-                        self.s += "<ul>\n";
-                    } else if nl.start == 1 {
-                        self.s += "<ol>\n";
-                    } else {
-                        self.s += &format!("<ol start=\"{}\">\n", nl.start);
-                    }
-                } else if nl.list_type == ListType::Bullet {
-                    self.s += "</ul>\n";
-                } else {
-                    self.s += "</ol>\n";
-                }
+            NodeValue::DescriptionList(ref ndl) => {
+                r.render_description_list(self, node, ndl, entering)
             }
-            NodeValue::Item(..) => {
-                if entering {
-                    self.cr();
-                    self.s += "<li>";
-                } else {
-                    self.s += "</li>\n";
-                }
+            NodeValue::DescriptionItem => r.render_description_item(self, node, entering),
+            NodeValue::DescriptionTerm => r.render_description_term(self, node, entering),
+            NodeValue::DescriptionDetails(ref ndd) => {
+                r.render_description_details(self, node, ndd, entering)
             }
-            NodeValue::Heading(ref nch) => {
-                if entering {
-                    self.cr();
-                    self.s += &format!("<h{}>", nch.level);
-                } else {
-                    self.s += &format!("</h{}>\n", nch.level);
-                }
+            NodeValue::MathBlock(ref ncb) => r.render_math_block(self, node, ncb, entering),
+            NodeValue::Text(ref literal) => r.render_text(self, node, literal, entering),
+            NodeValue::SoftBreak => r.render_soft_break(self, node, entering),
+            NodeValue::LineBreak => r.render_line_break(self, node, entering),
+            NodeValue::Code(ref literal) => r.render_code(self, node, literal, entering),
+            NodeValue::HtmlInline(ref literal) => {
+                r.render_html_inline(self, node, literal, entering)
             }
-            NodeValue::CodeBlock(ref ncb) => {
-                if entering {
-                    self.cr();
-
-                    if ncb.info.is_empty() {
-                        self.s += "<pre><code>";
-                    } else {
-                        let mut first_tag = 0;
-                        while first_tag < ncb.info.len() &&
-                            !isspace(ncb.info.as_bytes()[first_tag])
-                        {
-                            first_tag += 1;
-                        }
+            NodeValue::Emph => r.render_emph(self, node, entering),
+            NodeValue::Strong => r.render_strong(self, node, entering),
+            NodeValue::Strikethrough => r.render_strikethrough(self, node, entering),
+            NodeValue::Superscript => r.render_superscript(self, node, entering),
+            NodeValue::Subscript => r.render_subscript(self, node, entering),
+            NodeValue::Link(ref nl) => r.render_link(self, node, nl, entering),
+            NodeValue::Image(ref nl) => r.render_image(self, node, nl, entering),
+            NodeValue::FootnoteReference(ref name) => {
+                r.render_footnote_reference(self, node, name, entering)
+            }
+            NodeValue::Math(ref nm) => r.render_math(self, node, nm, entering),
+            NodeValue::TaskItem(checked) => r.render_task_item(self, node, checked, entering),
+        }
+    }
+
+    /// Renders the document root, closing the footnotes section if there is one.
+    pub fn render_document<'a>(&mut self, _: &'a AstNode<'a>, entering: bool) -> io::Result<bool> {
+        if !entering && self.footnote_ix > 0 {
+            self.output.write_all(b"</ol>\n</section>\n")?;
+        }
+        Ok(false)
+    }
+
+    /// Renders front matter, which is omitted from HTML output.
+    pub fn render_front_matter<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        _: &str,
+        _: bool,
+    ) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Renders a block quote.
+    pub fn render_block_quote<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.cr()?;
+            self.output.write_all(b"<blockquote")?;
+            self.render_sourcepos(node)?;
+            self.output.write_all(b">\n")?;
+        } else {
+            self.cr()?;
+            self.output.write_all(b"</blockquote>\n")?;
+        }
+        Ok(false)
+    }
+
+    /// Renders a list.
+    pub fn render_list<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        nl: &NodeList,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.cr()?;
+            if nl.list_type == ListType::Bullet {
+                self.output.write_all(b"<ul")?;
+            } else if nl.start == 1 {
+                self.output.write_all(b"<ol")?;
+            } else {
+                write!(self.output, "<ol start=\"{}\"", nl.start)?;
+            }
+            self.render_sourcepos(node)?;
+            self.output.write_all(b">\n")?;
+        } else if nl.list_type == ListType::Bullet {
+            self.output.write_all(b"</ul>\n")?;
+        } else {
+            self.output.write_all(b"</ol>\n")?;
+        }
+        Ok(false)
+    }
 
-                        if self.options.github_pre_lang {
-                            self.s += "<pre lang=\"";
-                            self.escape(&ncb.info[..first_tag]);
-                            self.s += "\"><code>";
-                        } else {
-                            self.s += "<pre><code class=\"language-";
-                            self.escape(&ncb.info[..first_tag]);
-                            self.s += "\">";
+    /// Renders a list item.
+    pub fn render_item<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        _: &NodeList,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.cr()?;
+            self.output.write_all(b"<li")?;
+            self.render_sourcepos(node)?;
+            self.output.write_all(b">")?;
+        } else {
+            self.output.write_all(b"</li>\n")?;
+        }
+        Ok(false)
+    }
+
+    /// Renders a code block, using the syntax highlighter for fenced code blocks if one was
+    /// given.
+    pub fn render_code_block<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        ncb: &NodeCodeBlock,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if !entering {
+            return Ok(false);
+        }
+
+        self.cr()?;
+
+        if ncb.fenced {
+            if let Some(highlighted) = self.highlighter
+                .and_then(|h| h.highlight(&ncb.info, &ncb.literal))
+            {
+                self.output.write_all(highlighted.as_bytes())?;
+                return Ok(false);
+            }
+        }
+
+        self.output.write_all(b"<pre")?;
+        self.render_sourcepos(node)?;
+
+        if ncb.info.is_empty() {
+            self.output.write_all(b"><code>")?;
+        } else {
+            let mut first_tag = 0;
+            while first_tag < ncb.info.len() && !isspace(ncb.info.as_bytes()[first_tag]) {
+                first_tag += 1;
+            }
+
+            if self.options.github_pre_lang {
+                self.output.write_all(b" lang=\"")?;
+                self.escape(&ncb.info[..first_tag])?;
+                self.output.write_all(b"\"><code>")?;
+            } else {
+                self.output.write_all(b"><code class=\"language-")?;
+                self.escape(&ncb.info[..first_tag])?;
+                self.output.write_all(b"\">")?;
+            }
+        }
+        self.escape(&ncb.literal)?;
+        self.output.write_all(b"</code></pre>\n")?;
+        Ok(false)
+    }
+
+    /// Renders an HTML block, subject to the `safe` and `ext_tagfilter` options.
+    pub fn render_html_block<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        nhb: &NodeHtmlBlock,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.cr()?;
+            if self.options.safe {
+                self.output.write_all(b"<!-- raw HTML omitted -->")?;
+            } else if self.options.ext_tagfilter {
+                tagfilter_block(&nhb.literal, &mut self.output)?;
+            } else {
+                self.output.write_all(nhb.literal.as_bytes())?;
+            }
+            self.cr()?;
+        }
+        Ok(false)
+    }
+
+    /// Renders a paragraph, omitting the `<p>` tags in tight lists and description terms.
+    pub fn render_paragraph<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        let tight = match node.parent() {
+            Some(parent) => {
+                match parent.data.borrow().value {
+                    NodeValue::DescriptionTerm => true,
+                    NodeValue::DescriptionDetails(..) => in_tight_description_list(parent),
+                    _ => {
+                        match parent.parent().map(|n| n.data.borrow().value.clone()) {
+                            Some(NodeValue::List(nl)) => nl.tight,
+                            _ => false,
                         }
                     }
-                    self.escape(&ncb.literal);
-                    self.s += "</code></pre>\n";
                 }
             }
-            NodeValue::HtmlBlock(ref nhb) => {
-                if entering {
-                    self.cr();
-                    if self.options.ext_tagfilter {
-                        tagfilter_block(&nhb.literal, &mut self.s);
-                    } else {
-                        self.s += &nhb.literal;
-                    }
-                    self.cr();
-                }
+            None => false,
+        };
+
+        if entering {
+            if !tight {
+                self.cr()?;
+                self.output.write_all(b"<p")?;
+                self.render_sourcepos(node)?;
+                self.output.write_all(b">")?;
             }
-            NodeValue::ThematicBreak => {
-                if entering {
-                    self.cr();
-                    self.s += "<hr />\n";
-                }
+        } else {
+            if self.in_last_footnote_paragraph(node) {
+                self.render_footnote_backref()?;
             }
-            NodeValue::Paragraph => {
-                let tight = match node.parent().and_then(|n| n.parent()).map(|n| {
-                    n.data.borrow().value.clone()
-                }) {
-                    Some(NodeValue::List(nl)) => nl.tight,
-                    _ => false,
-                };
-
-                if entering {
-                    if !tight {
-                        self.cr();
-                        self.s += "<p>";
-                    }
-                } else if !tight {
-                    self.s += "</p>\n";
-                }
+            if !tight {
+                self.output.write_all(b"</p>\n")?;
             }
-            NodeValue::Text(ref literal) => {
-                if entering {
-                    self.escape(literal);
-                }
+        }
+        Ok(false)
+    }
+
+    /// Renders a heading, with an anchor if the `ext_header_ids` option is set.
+    pub fn render_heading<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        nch: &NodeHeading,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.cr()?;
+            write!(self.output, "<h{}", nch.level)?;
+            self.render_sourcepos(node)?;
+            self.output.write_all(b">")?;
+
+            if let Some(ref prefix) = self.options.ext_header_ids {
+                let mut text = String::new();
+                collect_text(node, &mut text);
+                let id = self.anchorizer.anchorize(&text);
+                self.output.write_all(b"<a href=\"#")?;
+                self.escape(&id)?;
+                self.output.write_all(b"\" aria-hidden=\"true\" class=\"anchor\" id=\"")?;
+                self.escape(prefix)?;
+                self.escape(&id)?;
+                self.output.write_all(b"\"></a>")?;
             }
-            NodeValue::LineBreak => {
-                if entering {
-                    self.s += "<br />\n";
-                }
+        } else {
+            write!(self.output, "</h{}>\n", nch.level)?;
+        }
+        Ok(false)
+    }
+
+    /// Renders a thematic break.
+    pub fn render_thematic_break<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.cr()?;
+            self.output.write_all(b"<hr")?;
+            self.render_sourcepos(node)?;
+            self.output.write_all(b" />\n")?;
+        }
+        Ok(false)
+    }
+
+    /// Renders a table.
+    pub fn render_table<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        _: &[TableAlignment],
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.cr()?;
+            self.output.write_all(b"<table")?;
+            self.render_sourcepos(node)?;
+            self.output.write_all(b">\n")?;
+        } else {
+            if !node.last_child().unwrap().same_node(
+                node.first_child().unwrap(),
+            )
+            {
+                self.output.write_all(b"</tbody>")?;
             }
-            NodeValue::SoftBreak => {
-                if entering {
-                    if self.options.hardbreaks {
-                        self.s += "<br />\n";
-                    } else {
-                        self.s += "\n";
-                    }
-                }
+            self.output.write_all(b"</table>\n")?;
+        }
+        Ok(false)
+    }
+
+    /// Renders a table row, opening the table body after the header row.
+    pub fn render_table_row<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        header: bool,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.cr()?;
+            if header {
+                self.output.write_all(b"<thead>")?;
+                self.cr()?;
             }
-            NodeValue::Code(ref literal) => {
-                if entering {
-                    self.s += "<code>";
-                    self.escape(literal);
-                    self.s += "</code>";
-                }
+            self.output.write_all(b"<tr")?;
+            self.render_sourcepos(node)?;
+            self.output.write_all(b">")?;
+        } else {
+            self.cr()?;
+            self.output.write_all(b"</tr>")?;
+            if header {
+                self.cr()?;
+                self.output.write_all(b"</thead>")?;
+                self.cr()?;
+                self.output.write_all(b"<tbody>")?;
             }
-            NodeValue::HtmlInline(ref literal) => {
-                if entering {
-                    if self.options.ext_tagfilter && tagfilter(literal) {
-                        self.s += "&lt;";
-                        self.s += &literal[1..];
-                    } else {
-                        self.s += literal;
+        }
+        Ok(false)
+    }
+
+    /// Renders a table cell, aligned as its column is.
+    pub fn render_table_cell<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        let row = &node.parent().unwrap().data.borrow().value;
+        let in_header = match *row {
+            NodeValue::TableRow(header) => header,
+            _ => panic!(),
+        };
+
+        let table = &node.parent().unwrap().parent().unwrap().data.borrow().value;
+        let alignments = match *table {
+            NodeValue::Table(ref alignments) => alignments,
+            _ => panic!(),
+        };
+
+        if entering {
+            self.cr()?;
+            if in_header {
+                self.output.write_all(b"<th")?;
+            } else {
+                self.output.write_all(b"<td")?;
+            }
+
+            let mut start = node.parent().unwrap().first_child().unwrap();
+            let mut i = 0;
+            while !start.same_node(node) {
+                i += 1;
+                start = start.next_sibling().unwrap();
+            }
+
+            match alignments[i] {
+                TableAlignment::Left => self.output.write_all(b" align=\"left\"")?,
+                TableAlignment::Right => self.output.write_all(b" align=\"right\"")?,
+                TableAlignment::Center => self.output.write_all(b" align=\"center\"")?,
+                TableAlignment::None => (),
+            }
+
+            self.render_sourcepos(node)?;
+            self.output.write_all(b">")?;
+        } else if in_header {
+            self.output.write_all(b"</th>")?;
+        } else {
+            self.output.write_all(b"</td>")?;
+        }
+        Ok(false)
+    }
+
+    /// Renders a footnote definition as an item in the footnotes section, with a backref to each
+    /// reference to it.
+    pub fn render_footnote_definition<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        name: &str,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.footnote_ref_count = self.footnote_refs.get(name).map_or(1, |&(_, count)| count);
+            if self.footnote_ix == 0 {
+                self.cr()?;
+                self.output.write_all(b"<section class=\"footnotes\">\n<ol>\n")?;
+            }
+            self.footnote_ix += 1;
+            write!(self.output, "<li id=\"fn{}\">\n", self.footnote_ix)?;
+        } else {
+            let ends_in_paragraph = match node.last_child() {
+                Some(last) => {
+                    match last.data.borrow().value {
+                        NodeValue::Paragraph => true,
+                        _ => false,
                     }
                 }
+                None => false,
+            };
+            if !ends_in_paragraph {
+                self.render_footnote_backref()?;
+                self.cr()?;
             }
-            NodeValue::Strong => {
-                if entering {
-                    self.s += "<strong>";
-                } else {
-                    self.s += "</strong>";
-                }
+            self.output.write_all(b"</li>\n")?;
+        }
+        Ok(false)
+    }
+
+    /// Renders a description list.
+    pub fn render_description_list<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        _: &NodeDescriptionList,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.cr()?;
+            self.output.write_all(b"<dl")?;
+            self.render_sourcepos(node)?;
+            self.output.write_all(b">\n")?;
+        } else {
+            self.output.write_all(b"</dl>\n")?;
+        }
+        Ok(false)
+    }
+
+    /// Renders a description list item, which has no element of its own.
+    pub fn render_description_item<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        _: bool,
+    ) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Renders a description term.
+    pub fn render_description_term<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        self.render_description_tag(node, "dt", entering)
+    }
+
+    /// Renders description details.
+    pub fn render_description_details<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        _: &NodeDescriptionDetails,
+        entering: bool,
+    ) -> io::Result<bool> {
+        self.render_description_tag(node, "dd", entering)
+    }
+
+    /// Renders a math block as a `<div>`, with classes for client-side math renderers to find.
+    pub fn render_math_block<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        ncb: &NodeCodeBlock,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.cr()?;
+            self.output.write_all(b"<div class=\"math math-display\"")?;
+            self.render_sourcepos(node)?;
+            self.output.write_all(b">")?;
+            self.escape(&ncb.literal)?;
+            self.output.write_all(b"</div>\n")?;
+        }
+        Ok(false)
+    }
+
+    /// Renders text.
+    pub fn render_text<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        literal: &str,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.escape(literal)?;
+        }
+        Ok(false)
+    }
+
+    /// Renders a soft line break, as a hard line break if the `hardbreaks` option is set.
+    pub fn render_soft_break<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            if self.options.hardbreaks {
+                self.output.write_all(b"<br />\n")?;
+            } else {
+                self.output.write_all(b"\n")?;
             }
-            NodeValue::Emph => {
-                if entering {
-                    self.s += "<em>";
-                } else {
-                    self.s += "</em>";
-                }
+        }
+        Ok(false)
+    }
+
+    /// Renders a hard line break.
+    pub fn render_line_break<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.output.write_all(b"<br />\n")?;
+        }
+        Ok(false)
+    }
+
+    /// Renders an inline code span.
+    pub fn render_code<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        literal: &str,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.output.write_all(b"<code>")?;
+            self.escape(literal)?;
+            self.output.write_all(b"</code>")?;
+        }
+        Ok(false)
+    }
+
+    /// Renders inline HTML, subject to the `safe` and `ext_tagfilter` options.
+    pub fn render_html_inline<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        literal: &str,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            if self.options.safe {
+                self.output.write_all(b"<!-- raw HTML omitted -->")?;
+            } else if self.options.ext_tagfilter && tagfilter(literal) {
+                self.output.write_all(b"&lt;")?;
+                self.output.write_all(&literal.as_bytes()[1..])?;
+            } else {
+                self.output.write_all(literal.as_bytes())?;
             }
-            NodeValue::Strikethrough => {
-                if entering {
-                    self.s += "<del>";
-                } else {
-                    self.s += "</del>";
-                }
+        }
+        Ok(false)
+    }
+
+    /// Renders emphasis.
+    pub fn render_emph<'a>(&mut self, _: &'a AstNode<'a>, entering: bool) -> io::Result<bool> {
+        self.render_tag("em", entering)
+    }
+
+    /// Renders strong emphasis.
+    pub fn render_strong<'a>(&mut self, _: &'a AstNode<'a>, entering: bool) -> io::Result<bool> {
+        self.render_tag("strong", entering)
+    }
+
+    /// Renders strikethrough.
+    pub fn render_strikethrough<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        self.render_tag("del", entering)
+    }
+
+    /// Renders superscript.
+    pub fn render_superscript<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        self.render_tag("sup", entering)
+    }
+
+    /// Renders subscript.
+    pub fn render_subscript<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        entering: bool,
+    ) -> io::Result<bool> {
+        self.render_tag("sub", entering)
+    }
+
+    /// Renders a link, omitting dangerous URLs if the `safe` option is set.
+    pub fn render_link<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        nl: &NodeLink,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.output.write_all(b"<a href=\"")?;
+            if !(self.options.safe && scanners::dangerous_url(&nl.url)) {
+                self.escape_href(&nl.url)?;
             }
-            NodeValue::Superscript => {
-                if entering {
-                    self.s += "<sup>";
-                } else {
-                    self.s += "</sup>";
-                }
+            if !nl.title.is_empty() {
+                self.output.write_all(b"\" title=\"")?;
+                self.escape(&nl.title)?;
             }
-            NodeValue::Link(ref nl) => {
-                if entering {
-                    self.s += "<a href=\"";
-                    self.escape_href(&nl.url);
-                    if !nl.title.is_empty() {
-                        self.s += "\" title=\"";
-                        self.escape(&nl.title);
-                    }
-                    self.s += "\">";
-                } else {
-                    self.s += "</a>";
-                }
+            self.output.write_all(b"\">")?;
+        } else {
+            self.output.write_all(b"</a>")?;
+        }
+        Ok(false)
+    }
+
+    /// Renders an image, with its contents as plain text alt text, omitting dangerous URLs if the
+    /// `safe` option is set.
+    pub fn render_image<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        nl: &NodeLink,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.output.write_all(b"<img src=\"")?;
+            if !(self.options.safe && scanners::dangerous_url(&nl.url)) {
+                self.escape_href(&nl.url)?;
             }
-            NodeValue::Image(ref nl) => {
-                if entering {
-                    self.s += "<img src=\"";
-                    self.escape_href(&nl.url);
-                    self.s += "\" alt=\"";
-                    return true;
-                } else {
-                    if !nl.title.is_empty() {
-                        self.s += "\" title=\"";
-                        self.escape(&nl.title);
-                    }
-                    self.s += "\" />";
-                }
+            self.output.write_all(b"\" alt=\"")?;
+            return Ok(true);
+        }
+
+        if !nl.title.is_empty() {
+            self.output.write_all(b"\" title=\"")?;
+            self.escape(&nl.title)?;
+        }
+        self.output.write_all(b"\" />")?;
+        Ok(false)
+    }
+
+    /// Renders a footnote reference, numbering footnotes in order of first reference.  Repeated
+    /// references to the same footnote are given the IDs `fnref1-2`, `fnref1-3` and so on.
+    pub fn render_footnote_reference<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        name: &str,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            let next_ix = self.footnote_refs.len() as u32 + 1;
+            let entry = self.footnote_refs.entry(name.to_string()).or_insert((next_ix, 0));
+            entry.1 += 1;
+            let (ix, count) = *entry;
+            write!(
+                self.output,
+                "<sup class=\"footnote-ref\"><a href=\"#fn{}\" id=\"{}\">{}</a></sup>",
+                ix,
+                footnote_ref_id(ix, count),
+                ix
+            )?;
+        }
+        Ok(false)
+    }
+
+    /// Renders a math span as a `<span>`, with classes for client-side math renderers to find.
+    pub fn render_math<'a>(
+        &mut self,
+        _: &'a AstNode<'a>,
+        nm: &NodeMath,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            if nm.display_math {
+                self.output.write_all(b"<span class=\"math math-display\">")?;
+            } else {
+                self.output.write_all(b"<span class=\"math math-inline\">")?;
             }
-            NodeValue::Table(..) => {
-                if entering {
-                    self.cr();
-                    self.s += "<table>\n";
-                } else {
-                    if !node.last_child().unwrap().same_node(
-                        node.first_child().unwrap(),
-                    )
-                    {
-                        self.s += "</tbody>";
-                    }
-                    self.s += "</table>\n";
-                }
+            self.escape(&nm.literal)?;
+            self.output.write_all(b"</span>")?;
+        }
+        Ok(false)
+    }
+
+    /// Renders a task list item's checkbox as an `<input>`, followed by a space.  The checkbox is
+    /// disabled unless `tasklist_interactive` is set, in which case it's numbered instead.
+    pub fn render_task_item<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        checked: bool,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.output.write_all(b"<input type=\"checkbox\"")?;
+            if self.options.tasklist_interactive {
+                write!(
+                    self.output,
+                    " data-task-index=\"{}\" data-task-line=\"{}\"",
+                    self.task_ix,
+                    node.data.borrow().start_line
+                )?;
+                self.task_ix += 1;
+            } else {
+                self.output.write_all(b" disabled=\"\"")?;
             }
-            NodeValue::TableRow(header) => {
-                if entering {
-                    self.cr();
-                    if header {
-                        self.s += "<thead>";
-                        self.cr();
-                    }
-                    self.s += "<tr>";
-                } else {
-                    self.cr();
-                    self.s += "</tr>";
-                    if header {
-                        self.cr();
-                        self.s += "</thead>";
-                        self.cr();
-                        self.s += "<tbody>";
-                    }
-                }
+            if checked {
+                self.output.write_all(b" checked=\"\"")?;
             }
-            NodeValue::TableCell => {
-                let row = &node.parent().unwrap().data.borrow().value;
-                let in_header = match *row {
-                    NodeValue::TableRow(header) => header,
-                    _ => panic!(),
-                };
-
-                let table = &node.parent().unwrap().parent().unwrap().data.borrow().value;
-                let alignments = match *table {
-                    NodeValue::Table(ref alignments) => alignments,
-                    _ => panic!(),
-                };
-
-                if entering {
-                    self.cr();
-                    if in_header {
-                        self.s += "<th";
-                    } else {
-                        self.s += "<td";
-                    }
+            self.output.write_all(b" /> ")?;
+        }
+        Ok(false)
+    }
 
-                    let mut start = node.parent().unwrap().first_child().unwrap();
-                    let mut i = 0;
-                    while !start.same_node(node) {
-                        i += 1;
-                        start = start.next_sibling().unwrap();
-                    }
+    fn render_tag(&mut self, tag: &str, entering: bool) -> io::Result<bool> {
+        if entering {
+            write!(self.output, "<{}>", tag)?;
+        } else {
+            write!(self.output, "</{}>", tag)?;
+        }
+        Ok(false)
+    }
 
-                    match alignments[i] {
-                        TableAlignment::Left => self.s += " align=\"left\"",
-                        TableAlignment::Right => self.s += " align=\"right\"",
-                        TableAlignment::Center => self.s += " align=\"center\"",
-                        TableAlignment::None => (),
-                    }
+    fn render_description_tag<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        tag: &str,
+        entering: bool,
+    ) -> io::Result<bool> {
+        if entering {
+            self.cr()?;
+            write!(self.output, "<{}", tag)?;
+            self.render_sourcepos(node)?;
+            self.output.write_all(b">")?;
+        } else {
+            writeln!(self.output, "</{}>", tag)?;
+        }
+        Ok(false)
+    }
 
-                    self.s += ">";
-                } else if in_header {
-                    self.s += "</th>";
-                } else {
-                    self.s += "</td>";
+    fn in_last_footnote_paragraph<'a>(&self, node: &'a AstNode<'a>) -> bool {
+        node.next_sibling().is_none() &&
+            match node.parent() {
+                Some(parent) => {
+                    match parent.data.borrow().value {
+                        NodeValue::FootnoteDefinition(..) => true,
+                        _ => false,
+                    }
                 }
+                None => false,
+            }
+    }
+
+    fn render_footnote_backref(&mut self) -> io::Result<()> {
+        for count in 1..self.footnote_ref_count + 1 {
+            write!(
+                self.output,
+                " <a href=\"#{}\" class=\"footnote-backref\">↩",
+                footnote_ref_id(self.footnote_ix, count)
+            )?;
+            if count > 1 {
+                write!(self.output, "<sup>{}</sup>", count)?;
             }
+            self.output.write_all(b"</a>")?;
         }
-        false
+        Ok(())
     }
 }
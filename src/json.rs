@@ -0,0 +1,148 @@
+//! Serializing ASTs to JSON and back.  Requires the `serde` feature.
+//!
+//! Each node is represented as an object with its `type`, any `value` belonging to that type of
+//! node, its source position, and its `children`:
+//!
+//! ```json
+//! {"type": "Link", "value": {"url": "/", "title": ""},
+//!  "start_line": 1, "start_column": 1, "end_line": 1, "end_column": 7,
+//!  "children": [{"type": "Text", "value": "home", ...}]}
+//! ```
+
+use nodes::{AstNode, NodeValue};
+use serde::de::Error;
+use serde_json;
+use std::io::{self, Write};
+use typed_arena::Arena;
+
+#[derive(Serialize, Deserialize)]
+struct JsonNode {
+    #[serde(flatten)]
+    value: NodeValue,
+    #[serde(default)]
+    start_line: u32,
+    #[serde(default)]
+    start_column: usize,
+    #[serde(default)]
+    end_line: u32,
+    #[serde(default)]
+    end_column: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    children: Vec<JsonNode>,
+}
+
+impl JsonNode {
+    fn from_ast<'a>(node: &'a AstNode<'a>) -> Self {
+        let ast = node.data.borrow();
+        JsonNode {
+            value: ast.value.clone(),
+            start_line: ast.start_line,
+            start_column: ast.start_column,
+            end_line: ast.end_line,
+            end_column: ast.end_column,
+            children: node.children().map(JsonNode::from_ast).collect(),
+        }
+    }
+
+    fn into_ast<'a>(self, arena: &'a Arena<AstNode<'a>>) -> serde_json::Result<&'a AstNode<'a>> {
+        if let NodeValue::Table(ref alignments) = self.value {
+            if self.children.is_empty() {
+                return Err(serde_json::Error::custom("table has no rows"));
+            }
+            if self.children.iter().any(|row| row.children.len() > alignments.len()) {
+                return Err(serde_json::Error::custom(
+                    "table row has more cells than the table has columns",
+                ));
+            }
+        }
+
+        let node = AstNode::new_in(arena, self.value);
+        {
+            let mut ast = node.data.borrow_mut();
+            ast.start_line = self.start_line;
+            ast.start_column = self.start_column;
+            ast.end_line = self.end_line;
+            ast.end_column = self.end_column;
+        }
+
+        for child in self.children {
+            node.append_checked(child.into_ast(arena)?)
+                .map_err(serde_json::Error::custom)?;
+        }
+
+        Ok(node)
+    }
+}
+
+/// Formats an AST as JSON.
+pub fn format_document<'a>(root: &'a AstNode<'a>) -> String {
+    let mut output = vec![];
+    format_document_to(root, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+/// Formats an AST as JSON, writing the result to `output`.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, format_json_to, ComrakOptions};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "*Hi*\n", &options);
+///
+/// let mut output = vec![];
+/// format_json_to(root, &mut output).unwrap();
+/// assert_eq!(String::from_utf8(output).unwrap(),
+///            "{\"type\":\"Document\",\
+///             \"start_line\":0,\"start_column\":0,\"end_line\":1,\"end_column\":4,\
+///             \"children\":[{\"type\":\"Paragraph\",\
+///             \"start_line\":1,\"start_column\":1,\"end_line\":1,\"end_column\":4,\
+///             \"children\":[{\"type\":\"Emph\",\
+///             \"start_line\":1,\"start_column\":1,\"end_line\":1,\"end_column\":4,\
+///             \"children\":[{\"type\":\"Text\",\"value\":\"Hi\",\
+///             \"start_line\":1,\"start_column\":2,\"end_line\":1,\"end_column\":3}]}]}]}\n");
+/// # }
+/// ```
+pub fn format_document_to<'a>(root: &'a AstNode<'a>, output: &mut Write) -> io::Result<()> {
+    serde_json::to_writer(&mut *output, &JsonNode::from_ast(root))?;
+    output.write_all(b"\n")
+}
+
+/// Reads an AST from JSON in the format produced by `format_document`, allocating its nodes in
+/// `arena`.
+///
+/// Source positions may be omitted.  An error is returned if the JSON is malformed, or if the
+/// tree is not one the parser could have produced: each node must be allowed to contain its
+/// children, as checked by `nodes::can_contain_type`, and each table must have at least one row,
+/// none with more cells than the table has columns.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{format_commonmark, ComrakOptions};
+/// # use comrak::json::parse_document;
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let root = parse_document(&arena, r#"
+///     {"type": "Document", "children": [
+///         {"type": "Heading", "value": {"level": 1, "setext": false}, "children": [
+///             {"type": "Text", "value": "Hello"}]}]}"#).unwrap();
+///
+/// assert_eq!(format_commonmark(root, &ComrakOptions::default()), "# Hello\n");
+///
+/// assert!(parse_document(&arena, r#"{"type": "Document", "children": [
+///     {"type": "Text", "value": "Not in a paragraph"}]}"#).is_err());
+/// # }
+/// ```
+pub fn parse_document<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    json: &str,
+) -> serde_json::Result<&'a AstNode<'a>> {
+    let root: JsonNode = serde_json::from_str(json)?;
+    match root.value {
+        NodeValue::Document => root.into_ast(arena),
+        _ => Err(serde_json::Error::custom("root node must be a Document")),
+    }
+}
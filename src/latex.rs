@@ -0,0 +1,573 @@
+use nodes;
+use nodes::{AstNode, ListDelimType, ListType, NodeLink, NodeValue, TableAlignment};
+use parser::ComrakOptions;
+use std::io::{self, Write};
+use strings::normalize_reference_label;
+use wrap::{first_char, LineWriter, Syntax};
+
+/// Formats an AST as LaTeX, modified by the given options.
+///
+/// Only the document body is produced, unless `options.latex_standalone` is set, in which case a
+/// complete document with a preamble is.  Lines are wrapped to `options.width` if it is
+/// non-zero.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, format_latex, ComrakOptions};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "# Costs\n\nOnly *$5* & up.\n", &options);
+///
+/// assert_eq!(format_latex(root, &options),
+///            "\\section{Costs}\n\nOnly \\emph{\\$5} \\& up.\n");
+/// # }
+/// ```
+pub fn format_document<'a>(root: &'a AstNode<'a>, options: &ComrakOptions) -> String {
+    let mut output = vec![];
+    format_document_to(root, options, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+/// Formats an AST as LaTeX, modified by the given options, writing the result to `output`.
+pub fn format_document_to<'a>(
+    root: &'a AstNode<'a>,
+    options: &ComrakOptions,
+    output: &mut Write,
+) -> io::Result<()> {
+    if options.latex_standalone {
+        output.write_all(PREAMBLE.as_bytes())?;
+    }
+
+    let mut f = LatexFormatter::new(root, options);
+    if f.format_node(root, true) {
+        for n in root.children() {
+            f.format(n);
+            f.w.flush_lines(output)?;
+        }
+        f.format_node(root, false);
+    }
+    f.w.finish(output)?;
+
+    if options.latex_standalone {
+        output.write_all(b"\n\\end{document}\n")?;
+    }
+    Ok(())
+}
+
+const PREAMBLE: &str = "\\documentclass{article}\n\
+                        \\usepackage[utf8]{inputenc}\n\
+                        \\usepackage[T1]{fontenc}\n\
+                        \\usepackage{graphicx}\n\
+                        \\usepackage[normalem]{ulem}\n\
+                        \\usepackage{hyperref}\n\
+                        \n\
+                        \\begin{document}\n\
+                        \n";
+
+struct LatexFormatter<'a, 'o> {
+    root: &'a AstNode<'a>,
+    options: &'o ComrakOptions,
+    w: LineWriter,
+    footnotes_used: Vec<String>,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum Escaping {
+    Literal,
+    Normal,
+    URL,
+}
+
+impl<'a, 'o> Write for LatexFormatter<'a, 'o> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output(&String::from_utf8_lossy(buf), false, Escaping::Literal);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, 'o> LatexFormatter<'a, 'o> {
+    fn new(root: &'a AstNode<'a>, options: &'o ComrakOptions) -> Self {
+        LatexFormatter {
+            root: root,
+            options: options,
+            w: LineWriter::new(options.width, Syntax::Text),
+            footnotes_used: vec![],
+        }
+    }
+
+    fn output(&mut self, buf: &str, wrap: bool, escaping: Escaping) {
+        self.w.output(buf.as_bytes(), wrap, |w, rest| {
+            let (c, len) = first_char(rest);
+            let nextc = rest.get(len).map(|_| first_char(&rest[len..]).0);
+            outc(w, c, escaping, nextc);
+            len
+        });
+    }
+
+    fn cr(&mut self) {
+        self.w.cr();
+    }
+
+    fn blankline(&mut self) {
+        self.w.blankline();
+    }
+
+    fn format(&mut self, node: &'a AstNode<'a>) {
+        if self.format_node(node, true) {
+            self.format_children(node);
+            self.format_node(node, false);
+        }
+    }
+
+    fn format_children(&mut self, node: &'a AstNode<'a>) {
+        for n in node.children() {
+            self.format(n);
+        }
+    }
+
+    fn format_node(&mut self, node: &'a AstNode<'a>, entering: bool) -> bool {
+        let allow_wrap = self.options.width > 0 && !self.options.hardbreaks;
+
+        match node.data.borrow().value {
+            NodeValue::Document => (),
+            NodeValue::FrontMatter(..) |
+            NodeValue::HtmlBlock(..) => (),
+            NodeValue::BlockQuote => {
+                if entering {
+                    self.cr();
+                    write!(self, "\\begin{{quote}}").unwrap();
+                    self.cr();
+                } else {
+                    write!(self, "\\end{{quote}}").unwrap();
+                    self.blankline();
+                }
+            }
+            NodeValue::List(ref nl) => {
+                let environment = if nl.list_type == ListType::Bullet {
+                    "itemize"
+                } else {
+                    "enumerate"
+                };
+
+                if entering {
+                    self.cr();
+                    write!(self, "\\begin{{{}}}", environment).unwrap();
+                    self.cr();
+
+                    if nl.list_type == ListType::Ordered {
+                        let counter = enumerate_counter(node);
+                        if nl.delimiter == ListDelimType::Paren {
+                            write!(
+                                self,
+                                "\\def\\label{}{{\\arabic{{{}}})}}",
+                                counter,
+                                counter
+                            ).unwrap();
+                            self.cr();
+                        }
+                        if nl.start != 1 {
+                            // Each item steps the counter before it is shown.
+                            let before = nl.start as isize - 1;
+                            write!(self, "\\setcounter{{{}}}{{{}}}", counter, before).unwrap();
+                            self.cr();
+                        }
+                    }
+                } else {
+                    self.cr();
+                    write!(self, "\\end{{{}}}", environment).unwrap();
+                    self.blankline();
+                }
+            }
+            NodeValue::Item(..) => {
+                if entering {
+                    self.cr();
+                    write!(self, "\\item ").unwrap();
+                } else {
+                    self.cr();
+                }
+            }
+            NodeValue::Heading(ref nch) => {
+                if entering {
+                    self.cr();
+                    let command = match nch.level {
+                        1 => "section",
+                        2 => "subsection",
+                        3 => "subsubsection",
+                        4 => "paragraph",
+                        _ => "subparagraph",
+                    };
+                    write!(self, "\\{}{{", command).unwrap();
+                } else {
+                    write!(self, "}}").unwrap();
+                    self.blankline();
+                }
+            }
+            NodeValue::CodeBlock(ref ncb) => {
+                if entering {
+                    self.cr();
+                    write!(self, "\\begin{{verbatim}}").unwrap();
+                    self.cr();
+                    self.output(&ncb.literal, false, Escaping::Literal);
+                    self.cr();
+                    write!(self, "\\end{{verbatim}}").unwrap();
+                    self.blankline();
+                }
+            }
+            NodeValue::MathBlock(ref ncb) => {
+                if entering {
+                    self.cr();
+                    write!(self, "\\[").unwrap();
+                    self.cr();
+                    self.output(&ncb.literal, false, Escaping::Literal);
+                    self.cr();
+                    write!(self, "\\]").unwrap();
+                    self.blankline();
+                }
+            }
+            NodeValue::ThematicBreak => {
+                if entering {
+                    self.blankline();
+                    write!(
+                        self,
+                        "\\begin{{center}}\\rule{{0.5\\linewidth}}{{\\linethickness}}\\end{{center}}"
+                    ).unwrap();
+                    self.blankline();
+                }
+            }
+            NodeValue::Paragraph => {
+                // A description term's paragraph is the argument to `\item`, so isn't ended.
+                if !entering && !is_description_term(node) {
+                    if in_tight_list_item(node) {
+                        self.cr();
+                    } else {
+                        self.blankline();
+                    }
+                }
+            }
+            NodeValue::Text(ref literal) => {
+                if entering {
+                    self.output(literal, allow_wrap, Escaping::Normal);
+                }
+            }
+            NodeValue::LineBreak => {
+                if entering {
+                    write!(self, "\\\\").unwrap();
+                    self.cr();
+                }
+            }
+            NodeValue::SoftBreak => {
+                if entering {
+                    if self.options.hardbreaks {
+                        write!(self, "\\\\").unwrap();
+                        self.cr();
+                    } else if self.options.width == 0 {
+                        self.cr();
+                    } else {
+                        self.output(" ", allow_wrap, Escaping::Literal);
+                    }
+                }
+            }
+            NodeValue::Code(ref literal) => {
+                if entering {
+                    write!(self, "\\texttt{{").unwrap();
+                    self.output(literal, false, Escaping::Normal);
+                    write!(self, "}}").unwrap();
+                }
+            }
+            NodeValue::Math(ref nm) => {
+                if entering {
+                    let (open, close) = if nm.display_math {
+                        ("\\[", "\\]")
+                    } else {
+                        ("$", "$")
+                    };
+                    write!(self, "{}", open).unwrap();
+                    self.output(&nm.literal, false, Escaping::Literal);
+                    write!(self, "{}", close).unwrap();
+                }
+            }
+            NodeValue::HtmlInline(..) => (),
+            NodeValue::TaskItem(checked) => {
+                if entering {
+                    write!(self, "{}", if checked { "{[}x{]} " } else { "{[} {]} " }).unwrap();
+                }
+            }
+            NodeValue::Strong => {
+                write!(self, "{}", if entering { "\\textbf{" } else { "}" }).unwrap();
+            }
+            NodeValue::Emph => {
+                write!(self, "{}", if entering { "\\emph{" } else { "}" }).unwrap();
+            }
+            NodeValue::Strikethrough => {
+                write!(self, "{}", if entering { "\\sout{" } else { "}" }).unwrap();
+            }
+            NodeValue::Superscript => {
+                write!(self, "{}", if entering { "\\textsuperscript{" } else { "}" }).unwrap();
+            }
+            NodeValue::Subscript => {
+                write!(self, "{}", if entering { "\\textsubscript{" } else { "}" }).unwrap();
+            }
+            NodeValue::Link(ref nl) => {
+                if entering {
+                    if is_url_only(node, nl) {
+                        write!(self, "\\url{{").unwrap();
+                        self.output(&nl.url, false, Escaping::URL);
+                        write!(self, "}}").unwrap();
+                        return false;
+                    }
+                    write!(self, "\\href{{").unwrap();
+                    self.output(&nl.url, false, Escaping::URL);
+                    write!(self, "}}{{").unwrap();
+                } else {
+                    write!(self, "}}").unwrap();
+                }
+            }
+            NodeValue::Image(ref nl) => {
+                if entering {
+                    write!(self, "\\protect\\includegraphics{{").unwrap();
+                    self.output(&nl.url, false, Escaping::URL);
+                    write!(self, "}}").unwrap();
+                }
+                return false;
+            }
+            NodeValue::FootnoteDefinition(..) => {
+                // Definitions are rendered where they are first referenced.
+                return false;
+            }
+            NodeValue::DescriptionList(..) => {
+                if entering {
+                    self.cr();
+                    write!(self, "\\begin{{description}}").unwrap();
+                    self.cr();
+                } else {
+                    self.cr();
+                    write!(self, "\\end{{description}}").unwrap();
+                    self.blankline();
+                }
+            }
+            NodeValue::DescriptionItem => {
+                if !entering {
+                    self.cr();
+                }
+            }
+            NodeValue::DescriptionTerm => {
+                if entering {
+                    self.cr();
+                    write!(self, "\\item[{{").unwrap();
+                } else {
+                    write!(self, "}}] ").unwrap();
+                }
+            }
+            NodeValue::DescriptionDetails(..) => {
+                // Further details of the same term start a new paragraph.
+                if entering && node.previous_sibling().map_or(false, |previous| {
+                    match previous.data.borrow().value {
+                        NodeValue::DescriptionDetails(..) => true,
+                        _ => false,
+                    }
+                })
+                {
+                    self.blankline();
+                }
+                if !entering {
+                    self.cr();
+                }
+            }
+            NodeValue::FootnoteReference(ref name) => {
+                if entering {
+                    self.format_footnote_reference(name);
+                }
+            }
+            NodeValue::Table(ref alignments) => {
+                if entering {
+                    self.cr();
+                    write!(self, "\\begin{{tabular}}{{").unwrap();
+                    for a in alignments {
+                        write!(
+                            self,
+                            "{}",
+                            match *a {
+                                TableAlignment::Center => "c",
+                                TableAlignment::Right => "r",
+                                TableAlignment::Left |
+                                TableAlignment::None => "l",
+                            }
+                        ).unwrap();
+                    }
+                    write!(self, "}}").unwrap();
+                    self.cr();
+                } else {
+                    self.cr();
+                    write!(self, "\\end{{tabular}}").unwrap();
+                    self.blankline();
+                }
+            }
+            NodeValue::TableRow(header) => {
+                if entering {
+                    self.cr();
+                } else {
+                    write!(self, " \\\\").unwrap();
+                    if header {
+                        self.cr();
+                        write!(self, "\\hline").unwrap();
+                    }
+                    self.cr();
+                }
+            }
+            NodeValue::TableCell => {
+                if entering && node.previous_sibling().is_some() {
+                    write!(self, " & ").unwrap();
+                }
+            }
+        };
+        true
+    }
+
+    fn format_footnote_reference(&mut self, name: &str) {
+        let name = normalize_reference_label(name);
+        let mut ix = 0;
+        let mut definition = None;
+        for n in self.root.children() {
+            if let NodeValue::FootnoteDefinition(ref def_name) = n.data.borrow().value {
+                ix += 1;
+                if normalize_reference_label(def_name) == name {
+                    definition = Some(n);
+                    break;
+                }
+            }
+        }
+
+        let definition = match definition {
+            Some(definition) => definition,
+            None => return,
+        };
+
+        if self.footnotes_used.contains(&name) {
+            write!(self, "\\footnotemark[{}]", ix).unwrap();
+            return;
+        }
+
+        self.footnotes_used.push(name);
+        write!(self, "\\footnote{{").unwrap();
+        self.format_children(definition);
+        self.w.need_cr = 0;
+        write!(self, "}}").unwrap();
+    }
+}
+
+/// Returns the LaTeX counter used to number the given ordered list's items, which depends on how
+/// deeply it is nested in other ordered lists.
+fn enumerate_counter<'a>(node: &'a AstNode<'a>) -> &'static str {
+    let depth = node.ancestors()
+        .filter(|n| match n.data.borrow().value {
+            NodeValue::List(ref nl) => nl.list_type == ListType::Ordered,
+            _ => false,
+        })
+        .count();
+
+    match depth {
+        1 => "enumi",
+        2 => "enumii",
+        3 => "enumiii",
+        _ => "enumiv",
+    }
+}
+
+fn in_tight_list_item<'a>(node: &'a AstNode<'a>) -> bool {
+    let item = match node.parent() {
+        Some(item) => item,
+        None => return false,
+    };
+
+    match item.data.borrow().value {
+        NodeValue::Item(..) => (),
+        NodeValue::DescriptionDetails(..) => return nodes::in_tight_description_list(item),
+        _ => return false,
+    }
+
+    match item.parent().unwrap().data.borrow().value {
+        NodeValue::List(ref nl) => nl.tight,
+        _ => false,
+    }
+}
+
+fn is_description_term<'a>(node: &'a AstNode<'a>) -> bool {
+    node.parent().map_or(false, |parent| match parent.data.borrow().value {
+        NodeValue::DescriptionTerm => true,
+        _ => false,
+    })
+}
+
+fn is_url_only<'a>(node: &'a AstNode<'a>, nl: &NodeLink) -> bool {
+    let child = match node.first_child() {
+        Some(child) if child.next_sibling().is_none() => child,
+        _ => return false,
+    };
+
+    match child.data.borrow().value {
+        NodeValue::Text(ref text) => *text == nl.url,
+        _ => false,
+    }
+}
+
+fn outc(w: &mut LineWriter, c: char, escaping: Escaping, nextc: Option<char>) {
+    let escaped = match escaping {
+        Escaping::Literal => None,
+        Escaping::Normal => {
+            match c {
+                '{' => Some("\\{"),
+                '}' => Some("\\}"),
+                '#' => Some("\\#"),
+                '%' => Some("\\%"),
+                '&' => Some("\\&"),
+                '$' => Some("\\$"),
+                '_' => Some("\\_"),
+                '-' if nextc == Some('-') => Some("-{}"),
+                '~' => Some("\\textasciitilde{}"),
+                '^' => Some("\\^{}"),
+                '\\' => Some("\\textbackslash{}"),
+                '|' => Some("\\textbar{}"),
+                '<' => Some("\\textless{}"),
+                '>' => Some("\\textgreater{}"),
+                '[' => Some("{[}"),
+                ']' => Some("{]}"),
+                '"' => Some("\\textquotedbl{}"),
+                '\'' => Some("\\textquotesingle{}"),
+                '\u{a0}' => Some("~"),
+                '\u{2026}' => Some("\\ldots{}"),
+                '\u{2018}' => Some("`"),
+                '\u{2019}' => Some("'"),
+                '\u{201C}' => Some("``"),
+                '\u{201D}' => Some("''"),
+                '\u{2013}' => Some("--"),
+                '\u{2014}' => Some("---"),
+                _ => None,
+            }
+        }
+        Escaping::URL => {
+            match c {
+                '$' => Some("\\$"),
+                '%' => Some("\\%"),
+                '&' => Some("\\&"),
+                '_' => Some("\\_"),
+                '#' => Some("\\#"),
+                '^' => Some("\\^"),
+                '{' => Some("\\{"),
+                '}' => Some("\\}"),
+                '\\' => Some("\\\\"),
+                _ => None,
+            }
+        }
+    };
+
+    match escaped {
+        Some(escaped) => w.push_str(escaped),
+        None => w.push_str(c.encode_utf8(&mut [0; 4])),
+    }
+}
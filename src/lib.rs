@@ -81,23 +81,62 @@ extern crate regex;
 extern crate entities;
 #[macro_use]
 extern crate lazy_static;
+#[cfg(feature = "syntect")]
+extern crate syntect;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "serde")]
+extern crate serde_json;
 
 mod arena_tree;
 mod parser;
 mod scanners;
 mod html;
 mod cm;
+mod error;
+mod latex;
+mod man;
+mod plaintext;
+mod tasklist;
+mod xml;
+mod wrap;
 mod ctype;
 pub mod nodes;
+pub mod toc;
+pub mod visitor;
+#[cfg(feature = "syntect")]
+pub mod syntect_highlighter;
+#[cfg(feature = "serde")]
+pub mod json;
 mod entity;
 mod strings;
 #[cfg(test)]
 mod tests;
 
 pub use cm::format_document as format_commonmark;
+pub use cm::format_document_to as format_commonmark_to;
 pub use html::format_document as format_html;
+pub use html::format_document_to as format_html_to;
+#[cfg(feature = "serde")]
+pub use json::format_document as format_json;
+#[cfg(feature = "serde")]
+pub use json::format_document_to as format_json_to;
+pub use latex::format_document as format_latex;
+pub use latex::format_document_to as format_latex_to;
+pub use man::format_document as format_man;
+pub use man::format_document_to as format_man_to;
+pub use plaintext::format_document as format_plaintext;
+pub use plaintext::format_document_to as format_plaintext_to;
+pub use tasklist::toggle_task_item;
+pub use xml::format_document as format_xml;
+pub use xml::format_document_to as format_xml_to;
+pub use error::Error;
+pub use html::{Anchorizer, HtmlFormatter, HtmlRenderer, SyntaxHighlighter};
 
-pub use parser::{parse_document, ComrakOptions};
+pub use parser::{parse_document, ComrakOptions, StreamingParser};
 use typed_arena::Arena;
 
 /// Render Markdown to HTML.
@@ -15,30 +15,30 @@
 #![cfg_attr(feature = "dev", plugin(clippy))]
 #![allow(unknown_lints, doc_markdown, cyclomatic_complexity)]
 
-extern crate entities;
+extern crate comrak;
 #[macro_use]
 extern crate clap;
-extern crate unicode_categories;
 extern crate typed_arena;
-extern crate regex;
-#[macro_use]
-extern crate lazy_static;
-
-mod arena_tree;
-mod html;
-mod cm;
-mod parser;
-mod nodes;
-mod ctype;
-mod scanners;
-mod strings;
-mod entity;
 
+use comrak::{ComrakOptions, Error, StreamingParser};
 use std::collections::BTreeSet;
-use std::io::Read;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, Write};
 use std::process;
 use typed_arena::Arena;
 
+#[cfg(not(feature = "serde"))]
+const FORMATS: [&str; 6] = ["html", "commonmark", "xml", "plaintext", "man", "latex"];
+#[cfg(feature = "serde")]
+const FORMATS: [&str; 7] = ["html", "commonmark", "xml", "plaintext", "man", "latex", "json"];
+
+// Exit codes, following sysexits.h.
+const EXIT_USAGE: i32 = 64;
+const EXIT_DATAERR: i32 = 65;
+const EXIT_NOINPUT: i32 = 66;
+const EXIT_IOERR: i32 = 74;
+
 fn main() {
     let matches = clap::App::new(crate_name!())
         .version(crate_version!())
@@ -60,6 +60,20 @@ fn main() {
                 .long("github-pre-lang")
                 .help("Use GitHub-style <pre lang> for code blocks"),
         )
+        .arg(clap::Arg::with_name("sourcepos").long("sourcepos").help(
+            "Include source position attributes in HTML and XML output",
+        ))
+        .arg(
+            clap::Arg::with_name("tasklist-interactive")
+                .long("tasklist-interactive")
+                .help("Render enabled, numbered task list checkboxes in HTML output"),
+        )
+        .arg(clap::Arg::with_name("safe").long("safe").help(
+            "Suppress raw HTML and dangerous URLs",
+        ))
+        .arg(clap::Arg::with_name("smart").long("smart").help(
+            "Use smart punctuation",
+        ))
         .arg(
             clap::Arg::with_name("extension")
                 .short("e")
@@ -75,6 +89,10 @@ fn main() {
                         "autolink",
                         "tasklist",
                         "superscript",
+                        "subscript",
+                        "footnotes",
+                        "description-lists",
+                        "math",
                     ],
                 )
                 .value_name("EXTENSION")
@@ -85,11 +103,25 @@ fn main() {
                 .short("t")
                 .long("to")
                 .takes_value(true)
-                .possible_values(&["html", "commonmark"])
+                .possible_values(&FORMATS)
                 .default_value("html")
                 .value_name("FORMAT")
                 .help("Specify output format"),
         )
+        .arg(
+            clap::Arg::with_name("header-ids")
+                .long("header-ids")
+                .takes_value(true)
+                .value_name("PREFIX")
+                .help("Use the Comrak header IDs extension, with the given ID prefix"),
+        )
+        .arg(
+            clap::Arg::with_name("front-matter-delimiter")
+                .long("front-matter-delimiter")
+                .takes_value(true)
+                .value_name("DELIMITER")
+                .help("Ignore front matter that starts and ends with the given string"),
+        )
         .arg(
             clap::Arg::with_name("width")
                 .long("width")
@@ -98,53 +130,115 @@ fn main() {
                 .default_value("0")
                 .help("Specify wrap width (0 = nowrap)"),
         )
-        .get_matches();
+        .arg(
+            clap::Arg::with_name("latex-standalone")
+                .long("latex-standalone")
+                .help("Emit a complete LaTeX document, including the preamble"),
+        )
+        .get_matches_safe()
+        .unwrap_or_else(|err| if err.use_stderr() {
+            let _ = writeln!(io::stderr(), "{}", err.message);
+            process::exit(EXIT_USAGE);
+        } else {
+            err.exit();
+        });
 
     let mut exts = matches.values_of("extension").map_or(
         BTreeSet::new(),
         |vals| vals.collect(),
     );
 
-    let options = parser::ComrakOptions {
+    let options = ComrakOptions {
         hardbreaks: matches.is_present("hardbreaks"),
         github_pre_lang: matches.is_present("github-pre-lang"),
+        sourcepos: matches.is_present("sourcepos"),
+        tasklist_interactive: matches.is_present("tasklist-interactive"),
         width: matches.value_of("width").unwrap_or("0").parse().unwrap_or(
             0,
         ),
+        latex_standalone: matches.is_present("latex-standalone"),
+        safe: matches.is_present("safe"),
+        smart: matches.is_present("smart"),
         ext_strikethrough: exts.remove("strikethrough"),
         ext_tagfilter: exts.remove("tagfilter"),
         ext_table: exts.remove("table"),
         ext_autolink: exts.remove("autolink"),
         ext_tasklist: exts.remove("tasklist"),
         ext_superscript: exts.remove("superscript"),
+        ext_subscript: exts.remove("subscript"),
+        ext_footnotes: exts.remove("footnotes"),
+        ext_description_lists: exts.remove("description-lists"),
+        ext_math: exts.remove("math"),
+        ext_header_ids: matches.value_of("header-ids").map(|s| s.to_string()),
+        front_matter_delimiter: matches.value_of("front-matter-delimiter").map(|s| s.to_string()),
     };
 
-    assert!(exts.is_empty());
-
-    let mut s = String::with_capacity(2048);
+    let arena = Arena::new();
+    let mut parser = StreamingParser::new(&arena, &options);
 
     match matches.values_of("file") {
         None => {
-            std::io::stdin().read_to_string(&mut s).unwrap();
+            let stdin = io::stdin();
+            if let Err(err) = parser.read_from(stdin.lock()) {
+                fail_reading("<stdin>", err);
+            }
         }
         Some(fs) => {
             for f in fs {
-                let mut io = std::fs::File::open(f).unwrap();
-                io.read_to_string(&mut s).unwrap();
+                let file = match File::open(f) {
+                    Ok(file) => file,
+                    Err(err) => fail(EXIT_NOINPUT, format!("{}: {}", f, err)),
+                };
+                if let Err(err) = parser.read_from(file) {
+                    fail_reading(f, err);
+                }
             }
         }
     };
 
-    let arena = Arena::new();
-    let root = parser::parse_document(&arena, &s, &options);
+    let root = parser.finish();
 
     let formatter = match matches.value_of("format") {
-        Some("html") => html::format_document,
-        Some("commonmark") => cm::format_document,
-        _ => panic!("unknown format"),
+        Some("html") => comrak::format_html_to,
+        Some("commonmark") => comrak::format_commonmark_to,
+        Some("xml") => comrak::format_xml_to,
+        Some("plaintext") => comrak::format_plaintext_to,
+        Some("man") => comrak::format_man_to,
+        Some("latex") => comrak::format_latex_to,
+        #[cfg(feature = "serde")]
+        Some("json") => format_json,
+        _ => unreachable!(),
     };
 
-    print!("{}", formatter(root, &options));
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    if let Err(err) = formatter(root, &options, &mut stdout).and_then(|_| stdout.flush()) {
+        if err.kind() != io::ErrorKind::BrokenPipe {
+            fail(EXIT_IOERR, format!("<stdout>: {}", err));
+        }
+    }
 
     process::exit(0);
 }
+
+fn fail<M: Display>(code: i32, message: M) -> ! {
+    let _ = writeln!(io::stderr(), "comrak: {}", message);
+    process::exit(code);
+}
+
+fn fail_reading(name: &str, err: Error) -> ! {
+    let code = match err {
+        Error::Io(..) => EXIT_IOERR,
+        Error::InvalidUtf8 { .. } => EXIT_DATAERR,
+    };
+    fail(code, format!("{}: {}", name, err));
+}
+
+#[cfg(feature = "serde")]
+fn format_json<'a>(
+    root: &'a comrak::nodes::AstNode<'a>,
+    _options: &ComrakOptions,
+    output: &mut Write,
+) -> io::Result<()> {
+    comrak::format_json_to(root, output)
+}
@@ -0,0 +1,340 @@
+use nodes::{AstNode, ListDelimType, ListType, NodeValue, TableAlignment};
+use parser::ComrakOptions;
+use std::io::{self, Write};
+use wrap::{first_char, LineWriter, Syntax};
+
+/// Formats an AST as a groff man page body, modified by the given options.
+///
+/// Only the body is produced; a `.TH` title line should be written before it.  Lines are wrapped
+/// to `options.width` if it is non-zero.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, format_man, ComrakOptions};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "# Name\n\nfoo - do *all* the things\n", &options);
+///
+/// assert_eq!(format_man(root, &options),
+///            ".SH\nName\n.PP\nfoo \\- do \\f[I]all\\f[] the things\n");
+/// # }
+/// ```
+pub fn format_document<'a>(root: &'a AstNode<'a>, options: &ComrakOptions) -> String {
+    let mut output = vec![];
+    format_document_to(root, options, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+/// Formats an AST as a groff man page body, modified by the given options, writing the result to
+/// `output`.
+pub fn format_document_to<'a>(
+    root: &'a AstNode<'a>,
+    options: &ComrakOptions,
+    output: &mut Write,
+) -> io::Result<()> {
+    let mut f = ManFormatter::new(options);
+    if f.format_node(root, true) {
+        for n in root.children() {
+            f.format(n);
+            f.w.flush_lines(output)?;
+        }
+        f.format_node(root, false);
+    }
+    f.w.finish(output)
+}
+
+struct ManFormatter<'o> {
+    options: &'o ComrakOptions,
+    w: LineWriter,
+    in_table: bool,
+}
+
+impl<'o> Write for ManFormatter<'o> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output(&String::from_utf8_lossy(buf), false, false);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'o> ManFormatter<'o> {
+    fn new(options: &'o ComrakOptions) -> Self {
+        ManFormatter {
+            options: options,
+            w: LineWriter::new(options.width, Syntax::Roff),
+            in_table: false,
+        }
+    }
+
+    fn output(&mut self, buf: &str, wrap: bool, escape: bool) {
+        let wrap = wrap && !self.in_table;
+
+        self.w.output(buf.as_bytes(), wrap, |w, rest| if escape {
+            let (c, len) = first_char(rest);
+            outc(w, c);
+            len
+        } else {
+            w.push(rest[0]);
+            1
+        });
+    }
+
+    fn cr(&mut self) {
+        self.w.cr();
+    }
+
+    fn format<'a>(&mut self, node: &'a AstNode<'a>) {
+        if self.format_node(node, true) {
+            for n in node.children() {
+                self.format(n);
+            }
+            self.format_node(node, false);
+        }
+    }
+
+    fn format_node<'a>(&mut self, node: &'a AstNode<'a>, entering: bool) -> bool {
+        let allow_wrap = self.options.width > 0 && !self.options.hardbreaks;
+
+        match node.data.borrow().value {
+            NodeValue::Document |
+            NodeValue::FrontMatter(..) |
+            NodeValue::List(..) |
+            NodeValue::HtmlBlock(..) |
+            NodeValue::Strikethrough |
+            NodeValue::Superscript |
+            NodeValue::Subscript => (),
+            NodeValue::BlockQuote => {
+                self.cr();
+                write!(self, "{}", if entering { ".RS" } else { ".RE" }).unwrap();
+                self.cr();
+            }
+            NodeValue::Item(..) => {
+                if entering {
+                    let parent = match node.parent().unwrap().data.borrow().value {
+                        NodeValue::List(ref nl) => *nl,
+                        _ => unreachable!(),
+                    };
+
+                    self.cr();
+                    if parent.list_type == ListType::Bullet {
+                        write!(self, ".IP \\[bu] 2").unwrap();
+                    } else {
+                        write!(
+                            self,
+                            ".IP \"{}{}\" 4",
+                            parent.start + node.preceding_siblings().count() - 1,
+                            if parent.delimiter == ListDelimType::Paren {
+                                ")"
+                            } else {
+                                "."
+                            }
+                        ).unwrap();
+                    }
+                }
+                self.cr();
+            }
+            NodeValue::Heading(ref nch) => {
+                if entering {
+                    self.cr();
+                    write!(self, "{}", if nch.level == 1 { ".SH" } else { ".SS" }).unwrap();
+                }
+                self.cr();
+            }
+            NodeValue::CodeBlock(ref ncb) |
+            NodeValue::MathBlock(ref ncb) => {
+                if entering {
+                    self.cr();
+                    write!(self, ".IP\n.nf\n\\f[C]\n").unwrap();
+                    self.output(&ncb.literal, false, true);
+                    self.cr();
+                    write!(self, "\\f[]\n.fi").unwrap();
+                    self.cr();
+                }
+            }
+            NodeValue::ThematicBreak => {
+                if entering {
+                    self.cr();
+                    write!(self, ".PP\n  *  *  *  *  *").unwrap();
+                    self.cr();
+                }
+            }
+            NodeValue::Paragraph => {
+                let first_in_item = node.previous_sibling().is_none() &&
+                    match node.parent().unwrap().data.borrow().value {
+                        NodeValue::Item(..) |
+                        NodeValue::FootnoteDefinition(..) |
+                        NodeValue::DescriptionTerm |
+                        NodeValue::DescriptionDetails(..) => true,
+                        _ => false,
+                    };
+
+                if entering && !first_in_item {
+                    self.cr();
+                    write!(self, ".PP").unwrap();
+                }
+                self.cr();
+            }
+            NodeValue::Text(ref literal) => {
+                if entering {
+                    self.output(literal, allow_wrap, true);
+                }
+            }
+            NodeValue::LineBreak => {
+                if entering {
+                    self.cr();
+                    write!(self, ".PD 0\n.P\n.PD").unwrap();
+                    self.cr();
+                }
+            }
+            NodeValue::SoftBreak => {
+                if entering {
+                    if self.options.hardbreaks {
+                        self.cr();
+                        write!(self, ".PD 0\n.P\n.PD").unwrap();
+                        self.cr();
+                    } else if self.options.width == 0 {
+                        self.cr();
+                    } else {
+                        self.output(" ", allow_wrap, false);
+                    }
+                }
+            }
+            NodeValue::Code(ref literal) => {
+                if entering {
+                    write!(self, "\\f[C]").unwrap();
+                    self.output(literal, allow_wrap, true);
+                    write!(self, "\\f[]").unwrap();
+                }
+            }
+            NodeValue::Math(ref nm) => {
+                if entering {
+                    write!(self, "\\f[I]").unwrap();
+                    self.output(&nm.literal, false, true);
+                    write!(self, "\\f[]").unwrap();
+                }
+            }
+            NodeValue::HtmlInline(..) => (),
+            NodeValue::TaskItem(checked) => {
+                if entering {
+                    write!(self, "{}", if checked { "[x] " } else { "[ ] " }).unwrap();
+                }
+            }
+            NodeValue::Strong => {
+                write!(self, "{}", if entering { "\\f[B]" } else { "\\f[]" }).unwrap();
+            }
+            NodeValue::Emph => {
+                write!(self, "{}", if entering { "\\f[I]" } else { "\\f[]" }).unwrap();
+            }
+            NodeValue::Link(ref nl) => {
+                if !entering {
+                    self.output(" (", allow_wrap, false);
+                    self.output(&nl.url, false, true);
+                    write!(self, ")").unwrap();
+                }
+            }
+            NodeValue::Image(..) => {
+                write!(self, "{}", if entering { "[IMAGE: " } else { "]" }).unwrap();
+            }
+            NodeValue::FootnoteDefinition(ref name) => {
+                if entering {
+                    self.cr();
+                    write!(self, ".IP \"[").unwrap();
+                    self.output(name, false, true);
+                    write!(self, "]\" 4").unwrap();
+                }
+                self.cr();
+            }
+            NodeValue::DescriptionList(..) |
+            NodeValue::DescriptionItem => (),
+            NodeValue::DescriptionTerm => {
+                if entering {
+                    self.cr();
+                    write!(self, ".TP").unwrap();
+                }
+                self.cr();
+            }
+            NodeValue::DescriptionDetails(..) => {
+                // Further details of the same term continue the `.TP` body at its indentation.
+                if entering && node.previous_sibling().map_or(false, |previous| {
+                    match previous.data.borrow().value {
+                        NodeValue::DescriptionDetails(..) => true,
+                        _ => false,
+                    }
+                })
+                {
+                    self.cr();
+                    write!(self, ".IP").unwrap();
+                }
+                self.cr();
+            }
+            NodeValue::FootnoteReference(ref name) => {
+                if entering {
+                    write!(self, "[").unwrap();
+                    self.output(name, false, true);
+                    write!(self, "]").unwrap();
+                }
+            }
+            NodeValue::Table(ref alignments) => {
+                self.cr();
+                if entering {
+                    self.in_table = true;
+                    writeln!(self, ".TS").unwrap();
+                    for (i, a) in alignments.iter().enumerate() {
+                        write!(
+                            self,
+                            "{}{}",
+                            if i == 0 { "" } else { " " },
+                            match *a {
+                                TableAlignment::Center => "c",
+                                TableAlignment::Right => "r",
+                                TableAlignment::Left |
+                                TableAlignment::None => "l",
+                            }
+                        ).unwrap();
+                    }
+                    write!(self, ".").unwrap();
+                } else {
+                    self.in_table = false;
+                    write!(self, ".TE").unwrap();
+                }
+                self.cr();
+            }
+            NodeValue::TableRow(..) => {
+                if entering {
+                    self.cr();
+                }
+            }
+            NodeValue::TableCell => {
+                if entering && node.previous_sibling().is_some() {
+                    write!(self, "\t").unwrap();
+                }
+            }
+        };
+        true
+    }
+}
+
+fn outc(w: &mut LineWriter, c: char) {
+    let escaped = match c {
+        '.' if w.begin_line => "\\&.",
+        '\'' if w.begin_line => "\\&'",
+        '-' => "\\-",
+        '\\' => "\\e",
+        '\u{2018}' => "\\[oq]",
+        '\u{2019}' => "\\[cq]",
+        '\u{201C}' => "\\[lq]",
+        '\u{201D}' => "\\[rq]",
+        '\u{2013}' => "\\[en]",
+        '\u{2014}' => "\\[em]",
+        _ => {
+            w.push_str(c.encode_utf8(&mut [0; 4]));
+            return;
+        }
+    };
+    w.push_str(escaped);
+}
@@ -2,13 +2,30 @@
 
 use arena_tree::Node;
 use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use typed_arena::Arena;
 
 /// The core AST node enum.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
 pub enum NodeValue {
     /// The root of every CommonMark document.  Contains **blocks**.
     Document,
 
+    /// **Block**.  Front matter at the very start of the document, such as a YAML or TOML block,
+    /// enabled with the `front_matter_delimiter` option.  Contains the raw text of the block,
+    /// including delimiters and any blank lines following it, which is neither parsed as
+    /// Markdown nor rendered as HTML.
+    ///
+    /// ``` md
+    /// ---
+    /// title: Front matter
+    /// ---
+    /// ```
+    FrontMatter(String),
+
     /// **Block**. A [block quote](https://github.github.com/gfm/#block-quotes).  Contains other
     /// **blocks**.
     ///
@@ -65,6 +82,47 @@ pub enum NodeValue {
     /// **Block**.  A table cell.  Contains **inlines**.
     TableCell,
 
+    /// **Block**.  A footnote definition.  The `String` is the footnote's name.  Contains other
+    /// **blocks**.  Enabled with `ext_footnotes` option.
+    ///
+    /// ``` md
+    /// [^1]: A footnote.
+    /// ```
+    FootnoteDefinition(String),
+
+    /// **Block**.  A description list.  Contains description items.  Enabled with
+    /// `ext_description_lists` option.
+    ///
+    /// ``` md
+    /// Term
+    /// : The details of the term.
+    ///
+    /// Another term
+    /// : Its details.
+    /// ```
+    DescriptionList(NodeDescriptionList),
+
+    /// **Block**.  An item of a description list.  Contains a description term, followed by one
+    /// or more description details.
+    DescriptionItem,
+
+    /// **Block**.  The term of a description item.  Contains a paragraph.
+    DescriptionTerm,
+
+    /// **Block**.  The details of a description item.  Contains other **blocks**.
+    DescriptionDetails(NodeDescriptionDetails),
+
+    /// **Block**.  A fenced code block whose info string starts with `math`.  Contains raw TeX
+    /// which is not parsed as Markdown; the `NodeCodeBlock` keeps the details of the fence.
+    /// Enabled with `ext_math` option.
+    ///
+    /// ```` md
+    /// ``` math
+    /// e^{i\pi} + 1 = 0
+    /// ```
+    /// ````
+    MathBlock(NodeCodeBlock),
+
     /// **Inline**.  [Textual content](https://github.github.com/gfm/#textual-content).  All text
     /// in a document will be contained in a `Text` node.
     Text(String),
@@ -97,16 +155,33 @@ pub enum NodeValue {
     /// **Inline**.  Superscript.  Enabled with `ext_superscript` option.
     Superscript,
 
+    /// **Inline**.  Subscript.  Enabled with `ext_subscript` option.
+    Subscript,
+
     /// **Inline**.  A [link](https://github.github.com/gfm/#links) to some URL, with possible
     /// title.
     Link(NodeLink),
 
     /// **Inline**.  An [image](https://github.github.com/gfm/#images).
     Image(NodeLink),
+
+    /// **Inline**.  A reference to a footnote definition.  The `String` is the footnote's name.
+    /// Enabled with `ext_footnotes` option.
+    FootnoteReference(String),
+
+    /// **Inline**.  A math span, delimited by `$` for inline math or `$$` for display math.
+    /// Contains raw TeX which is not parsed as Markdown.  Enabled with `ext_math` option.
+    Math(NodeMath),
+
+    /// **Inline**.  The `[ ]` or `[x]` marker at the start of a task list item, which is always
+    /// the first node in the item's first paragraph.  The `bool` is whether it is checked.
+    /// Enabled with `ext_tasklist` option.
+    TaskItem(bool),
 }
 
 /// Alignment of a single table cell.
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum TableAlignment {
     /// Cell content is unaligned.
     None,
@@ -123,6 +198,7 @@ pub enum TableAlignment {
 
 /// The details of a link's destination, or an image's source.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NodeLink {
     /// The URL for the link destination or image source.
     pub url: String,
@@ -136,6 +212,7 @@ pub struct NodeLink {
 
 /// The metadata of a list; the kind of list, the delimiter used and so on.
 #[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NodeList {
     /// The kind of list (bullet (unordered) or ordered).
     pub list_type: ListType,
@@ -160,8 +237,30 @@ pub struct NodeList {
     pub tight: bool,
 }
 
+/// The metadata of a description list.
+#[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NodeDescriptionList {
+    /// Whether the list is tight, i.e. whether the paragraphs in its details are wrapped in `<p>`
+    /// tags when formatted as HTML.  A list is loose if any details are separated from their term
+    /// by a blank line, or contain blocks separated by blank lines.
+    pub tight: bool,
+}
+
+/// The metadata of a description details block.
+#[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NodeDescriptionDetails {
+    #[doc(hidden)]
+    pub marker_offset: usize,
+
+    #[doc(hidden)]
+    pub padding: usize,
+}
+
 /// The type of list.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ListType {
     /// A bullet list, i.e. an unordered list.
     Bullet,
@@ -178,6 +277,7 @@ impl Default for ListType {
 
 /// The delimiter for ordered lists, i.e. the character which appears after each number.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ListDelimType {
     /// A period character `.`.
     Period,
@@ -194,6 +294,7 @@ impl Default for ListDelimType {
 
 /// The metadata and data of a code block (fenced or indented).
 #[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NodeCodeBlock {
     /// Whether the code block is fenced.
     pub fenced: bool,
@@ -217,8 +318,20 @@ pub struct NodeCodeBlock {
     pub literal: String,
 }
 
+/// The details of a math span.
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NodeMath {
+    /// Whether the span is display math (delimited by `$$`), rather than inline math.
+    pub display_math: bool,
+
+    /// The literal TeX between the delimiters.
+    pub literal: String,
+}
+
 /// The metadata of a heading.
 #[derive(Default, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NodeHeading {
     /// The level of the header; from 1 to 6 for ATX headings, 1 or 2 for setext headings.
     pub level: u32,
@@ -229,6 +342,7 @@ pub struct NodeHeading {
 
 /// The metadata of an included HTML block.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NodeHtmlBlock {
     #[doc(hidden)]
     pub block_type: u8,
@@ -244,6 +358,7 @@ impl NodeValue {
     pub fn block(&self) -> bool {
         match *self {
             NodeValue::Document |
+            NodeValue::FrontMatter(..) |
             NodeValue::BlockQuote |
             NodeValue::List(..) |
             NodeValue::Item(..) |
@@ -254,7 +369,13 @@ impl NodeValue {
             NodeValue::ThematicBreak |
             NodeValue::Table(..) |
             NodeValue::TableRow(..) |
-            NodeValue::TableCell => true,
+            NodeValue::TableCell |
+            NodeValue::FootnoteDefinition(..) |
+            NodeValue::DescriptionList(..) |
+            NodeValue::DescriptionItem |
+            NodeValue::DescriptionTerm |
+            NodeValue::DescriptionDetails(..) |
+            NodeValue::MathBlock(..) => true,
             _ => false,
         }
     }
@@ -324,6 +445,8 @@ pub struct Ast {
     #[doc(hidden)]
     pub content: String,
     #[doc(hidden)]
+    pub line_offsets: Vec<usize>,
+    #[doc(hidden)]
     pub open: bool,
     #[doc(hidden)]
     pub last_line_blank: bool,
@@ -334,6 +457,7 @@ pub fn make_block(value: NodeValue, start_line: u32, start_column: usize) -> Ast
     Ast {
         value: value,
         content: String::new(),
+        line_offsets: Vec::new(),
         start_line: start_line,
         start_column: start_column,
         end_line: start_line,
@@ -343,35 +467,189 @@ pub fn make_block(value: NodeValue, start_line: u32, start_column: usize) -> Ast
     }
 }
 
+impl Ast {
+    /// Creates a node with the given value and no source position, ready to be inserted into a
+    /// document.
+    pub fn new(value: NodeValue) -> Self {
+        Ast {
+            value: value,
+            content: String::new(),
+            line_offsets: Vec::new(),
+            start_line: 0,
+            start_column: 0,
+            end_line: 0,
+            end_column: 0,
+            open: false,
+            last_line_blank: false,
+        }
+    }
+}
+
 /// The type of a node within the document.
 ///
 /// It is bound by the lifetime `'a`, which corresponds to the `Arena` nodes are allocated in.
 /// `AstNode`s are almost handled as a reference itself bound by `'a`.  Child `Ast`s are wrapped in
 /// `RefCell` for interior mutability.
+///
+/// Nodes can be created with `AstNode::new_in`, and added to a tree with the `*_checked` methods,
+/// which only allow nodes to be placed where the parser could have put them.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{format_html, ComrakOptions};
+/// # use comrak::nodes::{AstNode, NodeValue, NodeHeading};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let root = AstNode::new_in(&arena, NodeValue::Document);
+///
+/// let heading = AstNode::new_in(&arena, NodeValue::Heading(NodeHeading {
+///     level: 2,
+///     setext: false,
+/// }));
+/// root.append_checked(heading).unwrap();
+/// heading.append_checked(AstNode::new_in(&arena, NodeValue::Text("1.0.0".to_string())))
+///     .unwrap();
+///
+/// let paragraph = AstNode::new_in(&arena, NodeValue::Paragraph);
+/// assert!(root.append_checked(AstNode::new_in(&arena, NodeValue::Emph)).is_err());
+/// assert!(heading.insert_after_checked(paragraph).is_ok());
+///
+/// assert_eq!(format_html(root, &ComrakOptions::default()), "<h2>1.0.0</h2>\n<p></p>\n");
+/// # }
+/// ```
 pub type AstNode<'a> = Node<'a, RefCell<Ast>>;
 
+impl<'a> Node<'a, RefCell<Ast>> {
+    /// Allocates a new node with the given value in `arena`.  The node has no parent or children.
+    pub fn new_in(arena: &'a Arena<AstNode<'a>>, value: NodeValue) -> &'a AstNode<'a> {
+        arena.alloc(Node::new(RefCell::new(Ast::new(value))))
+    }
+
+    /// Appends `child` to this node's children, after checking this node can contain it.
+    pub fn append_checked(&'a self, child: &'a AstNode<'a>) -> Result<(), InvalidChild> {
+        check_not_ancestor(self, child)?;
+        check_can_contain(self, child)?;
+        self.append(child);
+        Ok(())
+    }
+
+    /// Prepends `child` to this node's children, after checking this node can contain it.
+    pub fn prepend_checked(&'a self, child: &'a AstNode<'a>) -> Result<(), InvalidChild> {
+        check_not_ancestor(self, child)?;
+        check_can_contain(self, child)?;
+        self.prepend(child);
+        Ok(())
+    }
+
+    /// Inserts `sibling` after this node, after checking this node's parent (if any) can contain
+    /// it.
+    pub fn insert_after_checked(&'a self, sibling: &'a AstNode<'a>) -> Result<(), InvalidChild> {
+        check_not_ancestor(self, sibling)?;
+        if let Some(parent) = self.parent() {
+            check_can_contain(parent, sibling)?;
+        }
+        self.insert_after(sibling);
+        Ok(())
+    }
+
+    /// Inserts `sibling` before this node, after checking this node's parent (if any) can contain
+    /// it.
+    pub fn insert_before_checked(&'a self, sibling: &'a AstNode<'a>) -> Result<(), InvalidChild> {
+        check_not_ancestor(self, sibling)?;
+        if let Some(parent) = self.parent() {
+            check_can_contain(parent, sibling)?;
+        }
+        self.insert_before(sibling);
+        Ok(())
+    }
+}
+
+fn check_can_contain<'a>(
+    node: &'a AstNode<'a>,
+    child: &'a AstNode<'a>,
+) -> Result<(), InvalidChild> {
+    if can_contain_type(node, &child.data.borrow().value) {
+        Ok(())
+    } else {
+        Err(InvalidChild {
+            parent: node.data.borrow().value.clone(),
+            child: child.data.borrow().value.clone(),
+            cycle: false,
+        })
+    }
+}
+
+fn check_not_ancestor<'a>(
+    node: &'a AstNode<'a>,
+    child: &'a AstNode<'a>,
+) -> Result<(), InvalidChild> {
+    if node.ancestors().any(|n| n.same_node(child)) {
+        Err(InvalidChild {
+            parent: node.data.borrow().value.clone(),
+            child: child.data.borrow().value.clone(),
+            cycle: true,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// The error returned when a node can't be inserted into a tree because its parent can't contain
+/// that kind of node, or because the node would end up inside itself.
+#[derive(Debug, Clone)]
+pub struct InvalidChild {
+    /// The value of the would-be parent.
+    pub parent: NodeValue,
+
+    /// The value of the node which couldn't be inserted.
+    pub child: NodeValue,
+
+    /// Whether the node couldn't be inserted because it is the would-be parent or one of its
+    /// ancestors.
+    pub cycle: bool,
+}
+
+impl fmt::Display for InvalidChild {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.cycle {
+            write!(f, "{:?} node cannot be inserted within itself", self.child)
+        } else {
+            write!(f, "{:?} node cannot contain {:?} node", self.parent, self.child)
+        }
+    }
+}
+
+impl Error for InvalidChild {
+    fn description(&self) -> &str {
+        "node cannot contain child"
+    }
+}
+
 #[doc(hidden)]
 pub fn last_child_is_open<'a>(node: &'a AstNode<'a>) -> bool {
     node.last_child().map_or(false, |n| n.data.borrow().open)
 }
 
-#[doc(hidden)]
+/// Indicates whether `node` may contain a child node with the value `child`.
 pub fn can_contain_type<'a>(node: &'a AstNode<'a>, child: &NodeValue) -> bool {
     if let NodeValue::Document = *child {
         return false;
     }
 
     match node.data.borrow().value {
-        NodeValue::Document |
-        NodeValue::BlockQuote |
-        NodeValue::Item(..) => {
-            child.block() &&
-                match *child {
-                    NodeValue::Item(..) => false,
-                    _ => true,
-                }
+        NodeValue::Document => {
+            match *child {
+                NodeValue::FrontMatter(..) => true,
+                _ => can_contain_block(child),
+            }
         }
 
+        NodeValue::BlockQuote |
+        NodeValue::FootnoteDefinition(..) |
+        NodeValue::Item(..) |
+        NodeValue::DescriptionDetails(..) => can_contain_block(child),
+
         NodeValue::List(..) => {
             match *child {
                 NodeValue::Item(..) => true,
@@ -379,10 +657,35 @@ pub fn can_contain_type<'a>(node: &'a AstNode<'a>, child: &NodeValue) -> bool {
             }
         }
 
+        NodeValue::DescriptionList(..) => {
+            match *child {
+                NodeValue::DescriptionItem => true,
+                _ => false,
+            }
+        }
+
+        NodeValue::DescriptionItem => {
+            match *child {
+                NodeValue::DescriptionTerm |
+                NodeValue::DescriptionDetails(..) => true,
+                _ => false,
+            }
+        }
+
+        NodeValue::DescriptionTerm => {
+            match *child {
+                NodeValue::Paragraph => true,
+                _ => false,
+            }
+        }
+
         NodeValue::Paragraph |
         NodeValue::Heading(..) |
         NodeValue::Emph |
         NodeValue::Strong |
+        NodeValue::Strikethrough |
+        NodeValue::Superscript |
+        NodeValue::Subscript |
         NodeValue::Link(..) |
         NodeValue::Image(..) => !child.block(),
 
@@ -409,6 +712,10 @@ pub fn can_contain_type<'a>(node: &'a AstNode<'a>, child: &NodeValue) -> bool {
                 NodeValue::Link(..) |
                 NodeValue::Image(..) |
                 NodeValue::Strikethrough |
+                NodeValue::Superscript |
+                NodeValue::Subscript |
+                NodeValue::FootnoteReference(..) |
+                NodeValue::Math(..) |
                 NodeValue::HtmlInline(..) => true,
                 _ => false,
             }
@@ -418,6 +725,21 @@ pub fn can_contain_type<'a>(node: &'a AstNode<'a>, child: &NodeValue) -> bool {
     }
 }
 
+/// Whether a container block other than a list, table or description list may contain `child`.
+fn can_contain_block(child: &NodeValue) -> bool {
+    child.block() &&
+        match *child {
+            NodeValue::Item(..) |
+            NodeValue::DescriptionItem |
+            NodeValue::DescriptionTerm |
+            NodeValue::DescriptionDetails(..) |
+            NodeValue::TableRow(..) |
+            NodeValue::TableCell |
+            NodeValue::FrontMatter(..) => false,
+            _ => true,
+        }
+}
+
 #[doc(hidden)]
 pub fn ends_with_blank_line<'a>(node: &'a AstNode<'a>) -> bool {
     let mut it = Some(node);
@@ -434,6 +756,19 @@ pub fn ends_with_blank_line<'a>(node: &'a AstNode<'a>) -> bool {
     false
 }
 
+#[doc(hidden)]
+pub fn in_tight_description_list<'a>(node: &'a AstNode<'a>) -> bool {
+    match node.parent().and_then(|item| item.parent()) {
+        Some(list) => {
+            match list.data.borrow().value {
+                NodeValue::DescriptionList(ref ndl) => ndl.tight,
+                _ => false,
+            }
+        }
+        None => false,
+    }
+}
+
 #[doc(hidden)]
 pub fn containing_block<'a>(node: &'a AstNode<'a>) -> Option<&'a AstNode<'a>> {
     let mut ch = Some(node);
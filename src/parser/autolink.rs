@@ -1,5 +1,6 @@
 use ctype::{isspace, isalpha, isalnum};
 use nodes::{NodeValue, NodeLink, AstNode};
+use parser::TextColumns;
 use parser::inlines::make_inline;
 use typed_arena::Arena;
 use unicode_categories::UnicodeCategories;
@@ -8,50 +9,91 @@ pub fn process_autolinks<'a>(
     arena: &'a Arena<AstNode<'a>>,
     node: &'a AstNode<'a>,
     contents: &mut String,
-) {
-    let len = contents.len();
-    let mut i = 0;
-
-    while i < len {
-        let mut post_org = None;
-
-        while i < len {
-            match contents.as_bytes()[i] {
-                b':' => {
-                    post_org = url_match(arena, contents, i);
-                    if post_org.is_some() {
-                        break;
-                    }
-                }
-                b'w' => {
-                    post_org = www_match(arena, contents, i);
-                    if post_org.is_some() {
-                        break;
-                    }
-                }
-                b'@' => {
-                    post_org = email_match(arena, contents, i);
-                    if post_org.is_some() {
-                        break;
-                    }
+    line: u32,
+    columns: &TextColumns,
+    end_column: &mut usize,
+) -> &'a AstNode<'a> {
+    let (post, i, skip) = match find_autolink(arena, contents) {
+        Some(m) => m,
+        None => return node,
+    };
+
+    let last_column = *end_column;
+    let mut rest = contents[i + skip..].to_string();
+    let mut offset = i + skip;
+    contents.truncate(i);
+    *end_column = columns.end_before(i);
+
+    set_link_position(post, line, columns.start(i), columns.end(offset - 1));
+    node.insert_after(post);
+    let mut last = post;
+
+    while !rest.is_empty() {
+        match find_autolink(arena, &rest) {
+            None => {
+                let remain = make_inline(arena, NodeValue::Text(rest));
+                set_position(remain, line, columns.start(offset), last_column);
+                last.insert_after(remain);
+                return remain;
+            }
+            Some((post, i, skip)) => {
+                if i > 0 {
+                    let remain = make_inline(arena, NodeValue::Text(rest[..i].to_string()));
+                    let end = columns.end_before(offset + i);
+                    set_position(remain, line, columns.start(offset), end);
+                    last.insert_after(remain);
+                    last = remain;
                 }
-                _ => (),
+                set_link_position(
+                    post,
+                    line,
+                    columns.start(offset + i),
+                    columns.end(offset + i + skip - 1),
+                );
+                last.insert_after(post);
+                last = post;
+                rest = rest[i + skip..].to_string();
+                offset += i + skip;
             }
-            i += 1;
         }
+    }
+
+    last
+}
+
+/// Finds the first autolink in `contents`, returning the link node, and the offset and length
+/// of the text it replaces.
+fn find_autolink<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    contents: &str,
+) -> Option<(&'a AstNode<'a>, usize, usize)> {
+    for i in 0..contents.len() {
+        let post_org = match contents.as_bytes()[i] {
+            b':' => url_match(arena, contents, i),
+            b'w' => www_match(arena, contents, i),
+            b'@' => email_match(arena, contents, i),
+            _ => None,
+        };
 
         if let Some((post, reverse, skip)) = post_org {
-            i -= reverse;
-            node.insert_after(post);
-            if i + skip < len {
-                let remain = contents[i + skip..].to_string();
-                assert!(!remain.is_empty());
-                post.insert_after(make_inline(arena, NodeValue::Text(remain)));
-            }
-            contents.truncate(i);
-            return;
+            return Some((post, i - reverse, skip));
         }
     }
+
+    None
+}
+
+fn set_link_position<'a>(node: &'a AstNode<'a>, line: u32, start_column: usize, end_column: usize) {
+    set_position(node, line, start_column, end_column);
+    set_position(node.first_child().unwrap(), line, start_column, end_column);
+}
+
+fn set_position<'a>(node: &'a AstNode<'a>, line: u32, start_column: usize, end_column: usize) {
+    let mut ast = node.data.borrow_mut();
+    ast.start_line = line;
+    ast.start_column = start_column;
+    ast.end_line = line;
+    ast.end_column = end_column;
 }
 
 fn www_match<'a>(
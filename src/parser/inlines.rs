@@ -1,11 +1,10 @@
-use arena_tree::Node;
-use ctype::{isspace, ispunct};
+use ctype::{isspace, ispunct, isdigit};
 use entity;
-use nodes::{NodeValue, Ast, NodeLink, AstNode};
+use nodes::{NodeValue, NodeLink, NodeMath, AstNode};
 use parser::{unwrap_into, unwrap_into_copy, ComrakOptions, Reference, AutolinkType};
 use scanners;
 
-use std::cell::{Cell, RefCell};
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::ptr;
 use strings;
@@ -21,12 +20,16 @@ pub struct Subject<'a: 'd, 'r, 'o, 'd> {
     pub input: String,
     pub pos: usize,
     pub refmap: &'r mut HashMap<String, Reference>,
+    footnote_defs: &'r HashMap<String, String>,
     delimiter_arena: &'d Arena<Delimiter<'a, 'd>>,
     last_delimiter: Option<&'d Delimiter<'a, 'd>>,
     brackets: Vec<Bracket<'a, 'd>>,
     pub backticks: [usize; MAXBACKTICKS + 1],
     pub scanned_for_backticks: bool,
     special_chars: Vec<bool>,
+    line: u32,
+    line_starts: Vec<usize>,
+    line_offsets: Vec<usize>,
 }
 
 pub struct Delimiter<'a: 'd, 'd> {
@@ -52,21 +55,35 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
         arena: &'a Arena<AstNode<'a>>,
         options: &'o ComrakOptions,
         input: &str,
+        line: u32,
+        line_offsets: &[usize],
         refmap: &'r mut HashMap<String, Reference>,
+        footnote_defs: &'r HashMap<String, String>,
         delimiter_arena: &'d Arena<Delimiter<'a, 'd>>,
     ) -> Self {
+        let mut line_starts = vec![0];
+        for (i, c) in input.bytes().enumerate() {
+            if c == b'\n' {
+                line_starts.push(i + 1);
+            }
+        }
+
         let mut s = Subject {
             arena: arena,
             options: options,
             input: input.to_string(),
             pos: 0,
             refmap: refmap,
+            footnote_defs: footnote_defs,
             delimiter_arena: delimiter_arena,
             last_delimiter: None,
             brackets: vec![],
             backticks: [0; MAXBACKTICKS + 1],
             scanned_for_backticks: false,
             special_chars: vec![],
+            line,
+            line_starts,
+            line_offsets: line_offsets.to_vec(),
         };
         s.special_chars.extend_from_slice(&[false; 256]);
         for &c in &[
@@ -86,12 +103,20 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
         {
             s.special_chars[c as usize] = true;
         }
-        if options.ext_strikethrough {
+        if options.ext_strikethrough || options.ext_subscript {
             s.special_chars[b'~' as usize] = true;
         }
         if options.ext_superscript {
             s.special_chars[b'^' as usize] = true;
         }
+        if options.ext_math {
+            s.special_chars[b'$' as usize] = true;
+        }
+        if options.smart {
+            for &c in &[b'\'', b'-', b'.'] {
+                s.special_chars[c as usize] = true;
+            }
+        }
         s
     }
 
@@ -101,6 +126,8 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
 
     pub fn parse_inline(&mut self, node: &'a AstNode<'a>) -> bool {
         let new_inl: Option<&'a AstNode<'a>>;
+        let mut endpos: Option<usize> = None;
+        let startpos = self.pos;
         let c = match self.peek_char() {
             None => return false,
             Some(ch) => *ch as char,
@@ -108,15 +135,17 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
 
         match c {
             '\0' => return false,
-            '\r' | '\n' => new_inl = Some(self.handle_newline()),
+            '\r' | '\n' => {
+                new_inl = Some(self.handle_newline());
+                endpos = Some(startpos + 1);
+            }
             '`' => new_inl = Some(self.handle_backticks()),
             '\\' => new_inl = Some(self.handle_backslash()),
             '&' => new_inl = Some(self.handle_entity()),
             '<' => new_inl = Some(self.handle_pointy_brace()),
             '*' | '_' | '\'' | '"' => new_inl = Some(self.handle_delim(c as u8)),
-            // TODO: smart characters. Eh.
-            //'-' => new_inl => Some(self.handle_hyphen()),
-            //'.' => new_inl => Some(self.handle_period()),
+            '-' => new_inl = Some(self.handle_hyphen()),
+            '.' => new_inl = Some(self.handle_period()),
             '[' => {
                 self.pos += 1;
                 let inl = make_inline(self.arena, NodeValue::Text("[".to_string()));
@@ -136,14 +165,16 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
                 }
             }
             _ => {
-                if self.options.ext_strikethrough && c == '~' {
+                if (self.options.ext_strikethrough || self.options.ext_subscript) && c == '~' {
                     new_inl = Some(self.handle_delim(b'~'));
                 } else if self.options.ext_superscript && c == '^' {
                     new_inl = Some(self.handle_delim(b'^'));
+                } else if self.options.ext_math && c == '$' {
+                    new_inl = Some(self.handle_dollars());
                 } else {
-                    let endpos = self.find_special_char();
-                    let mut contents = self.input[self.pos..endpos].to_string();
-                    self.pos = endpos;
+                    let special = self.find_special_char();
+                    let mut contents = self.input[self.pos..special].to_string();
+                    self.pos = special;
 
                     if self.peek_char().map_or(
                         false,
@@ -153,18 +184,47 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
                         strings::rtrim(&mut contents);
                     }
 
+                    endpos = Some(startpos + contents.len());
                     new_inl = Some(make_inline(self.arena, NodeValue::Text(contents)));
                 }
             }
         }
 
         if let Some(inl) = new_inl {
+            self.set_position(inl, startpos, endpos.unwrap_or(self.pos));
             node.append(inl);
         }
 
         true
     }
 
+    fn position(&self, pos: usize) -> (u32, usize) {
+        let ix = match self.line_starts.binary_search(&pos) {
+            Ok(ix) => ix,
+            Err(ix) => ix - 1,
+        };
+        let line_offset = self.line_offsets.get(ix).cloned().unwrap_or(0);
+        (
+            self.line + ix as u32,
+            line_offset + pos - self.line_starts[ix] + 1,
+        )
+    }
+
+    fn set_position(&self, inl: &'a AstNode<'a>, startpos: usize, endpos: usize) {
+        let (start_line, start_column) = self.position(startpos);
+        let (end_line, end_column) = self.position(if endpos > startpos {
+            endpos - 1
+        } else {
+            startpos
+        });
+
+        let mut ast = inl.data.borrow_mut();
+        ast.start_line = start_line;
+        ast.start_column = start_column;
+        ast.end_line = end_line;
+        ast.end_column = end_column;
+    }
+
     fn del_ref_eq(lhs: Option<&'d Delimiter<'a, 'd>>, rhs: Option<&'d Delimiter<'a, 'd>>) -> bool {
         match (lhs, rhs) {
             (None, None) => true,
@@ -207,8 +267,16 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
                             [closer.unwrap().delim_char as usize],
                     )
                 {
+                    // With subscripts enabled, `~` only matches a run of the same length, so
+                    // subscript and strikethrough don't close each other.
+                    let tilde_mismatch = self.options.ext_subscript &&
+                        closer.unwrap().delim_char == b'~' &&
+                        opener.unwrap().inl.data.borrow().value.text().map(|t| t.len()) !=
+                            closer.unwrap().inl.data.borrow().value.text().map(|t| t.len());
+
                     if opener.unwrap().can_open &&
-                        opener.unwrap().delim_char == closer.unwrap().delim_char
+                        opener.unwrap().delim_char == closer.unwrap().delim_char &&
+                        !tilde_mismatch
                     {
                         let odd_match = (closer.unwrap().can_open || opener.unwrap().can_close) &&
                             ((opener
@@ -238,9 +306,19 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
                 }
 
                 let old_closer = closer;
+                let old_closer_ix = old_closer
+                    .unwrap()
+                    .inl
+                    .data
+                    .borrow()
+                    .value
+                    .text()
+                    .unwrap()
+                    .len() % 3;
 
                 if closer.unwrap().delim_char == b'*' || closer.unwrap().delim_char == b'_' ||
-                    (self.options.ext_strikethrough && closer.unwrap().delim_char == b'~') ||
+                    ((self.options.ext_strikethrough || self.options.ext_subscript) &&
+                         closer.unwrap().delim_char == b'~') ||
                     (self.options.ext_superscript && closer.unwrap().delim_char == b'^')
                 {
                     if opener_found {
@@ -290,16 +368,7 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
                     closer = closer.unwrap().next.get();
                 }
                 if !opener_found {
-                    let ix = old_closer
-                        .unwrap()
-                        .inl
-                        .data
-                        .borrow()
-                        .value
-                        .text()
-                        .unwrap()
-                        .len() % 3;
-                    openers_bottom[ix][old_closer.unwrap().delim_char as usize] =
+                    openers_bottom[old_closer_ix][old_closer.unwrap().delim_char as usize] =
                         old_closer.unwrap().prev.get();
                     if !old_closer.unwrap().can_open {
                         self.remove_delimiter(old_closer.unwrap());
@@ -319,6 +388,17 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
     }
 
     fn remove_delimiter(&mut self, delimiter: &'d Delimiter<'a, 'd>) {
+        // Smart quotes which were never matched as a closer are left as openers.
+        if delimiter.delim_char == b'\'' || delimiter.delim_char == b'"' {
+            let mut ast = delimiter.inl.data.borrow_mut();
+            let text = ast.value.text_mut().unwrap();
+            if text == "'" {
+                *text = "’".to_string();
+            } else if text == "\"" {
+                *text = "“".to_string();
+            }
+        }
+
         if delimiter.next.get().is_none() {
             assert!(ptr::eq(delimiter, self.last_delimiter.unwrap()));
             self.last_delimiter = delimiter.prev.get();
@@ -427,6 +507,59 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
         }
     }
 
+    pub fn scan_to_closing_dollars(&mut self, opendollarlength: usize) -> Option<usize> {
+        let bytes = self.input.as_bytes();
+        let start = self.pos;
+        let mut pos = start;
+
+        if opendollarlength == 1 && bytes.get(pos).map_or(true, |&c| isspace(c)) {
+            return None;
+        }
+
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b'\\' => pos += 2,
+                b'$' => {
+                    if opendollarlength == 2 {
+                        if pos > start && bytes.get(pos + 1) == Some(&b'$') {
+                            return Some(pos + 2);
+                        }
+                    } else if !isspace(bytes[pos - 1]) &&
+                               !bytes.get(pos + 1).map_or(false, |&c| isdigit(c))
+                    {
+                        return Some(pos + 1);
+                    }
+                    pos += 1;
+                }
+                _ => pos += 1,
+            }
+        }
+
+        None
+    }
+
+    pub fn handle_dollars(&mut self) -> &'a AstNode<'a> {
+        let opendollars = self.take_while(b'$');
+        if opendollars.len() > 2 {
+            return make_inline(self.arena, NodeValue::Text(opendollars));
+        }
+
+        match self.scan_to_closing_dollars(opendollars.len()) {
+            None => make_inline(self.arena, NodeValue::Text(opendollars)),
+            Some(endpos) => {
+                let literal = self.input[self.pos..endpos - opendollars.len()].to_string();
+                self.pos = endpos;
+                make_inline(
+                    self.arena,
+                    NodeValue::Math(NodeMath {
+                        display_math: opendollars.len() == 2,
+                        literal: literal,
+                    }),
+                )
+            }
+        }
+    }
+
     pub fn skip_spaces(&mut self) -> bool {
         let mut skipped = false;
         while self.peek_char().map_or(false, |&c| c == b' ' || c == b'\t') {
@@ -439,16 +572,76 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
     pub fn handle_delim(&mut self, c: u8) -> &'a AstNode<'a> {
         let (numdelims, can_open, can_close) = self.scan_delims(c);
 
-        let contents = self.input[self.pos - numdelims..self.pos].to_string();
+        let pushed = (can_open || can_close) &&
+            (c != b'\'' && c != b'"' || self.options.smart) &&
+            (c != b'~' || !self.options.ext_subscript || numdelims == 1 ||
+                 (numdelims == 2 && self.options.ext_strikethrough));
+
+        let contents = if self.options.smart && !pushed && c == b'\'' {
+            "’".to_string()
+        } else if self.options.smart && !pushed && c == b'"' {
+            "“".to_string()
+        } else {
+            self.input[self.pos - numdelims..self.pos].to_string()
+        };
         let inl = make_inline(self.arena, NodeValue::Text(contents));
 
-        if (can_open || can_close) && c != b'\'' && c != b'"' {
+        if pushed {
             self.push_delimiter(c, can_open, can_close, inl);
         }
 
         inl
     }
 
+    pub fn handle_hyphen(&mut self) -> &'a AstNode<'a> {
+        let start = self.pos;
+        self.pos += 1;
+
+        if !self.options.smart || self.peek_char() != Some(&(b'-')) {
+            return make_inline(self.arena, NodeValue::Text("-".to_string()));
+        }
+
+        while self.peek_char() == Some(&(b'-')) {
+            self.pos += 1;
+        }
+
+        let num_hyphens = self.pos - start;
+        let (en_count, em_count) = if num_hyphens % 3 == 0 {
+            (0, num_hyphens / 3)
+        } else if num_hyphens % 2 == 0 {
+            (num_hyphens / 2, 0)
+        } else if num_hyphens % 3 == 2 {
+            (1, (num_hyphens - 2) / 3)
+        } else {
+            (2, (num_hyphens - 4) / 3)
+        };
+
+        let mut contents = String::new();
+        for _ in 0..em_count {
+            contents += "—";
+        }
+        for _ in 0..en_count {
+            contents += "–";
+        }
+
+        make_inline(self.arena, NodeValue::Text(contents))
+    }
+
+    pub fn handle_period(&mut self) -> &'a AstNode<'a> {
+        self.pos += 1;
+        if self.options.smart && self.peek_char() == Some(&(b'.')) {
+            self.pos += 1;
+            if self.peek_char() == Some(&(b'.')) {
+                self.pos += 1;
+                make_inline(self.arena, NodeValue::Text("…".to_string()))
+            } else {
+                make_inline(self.arena, NodeValue::Text("..".to_string()))
+            }
+        } else {
+            make_inline(self.arena, NodeValue::Text(".".to_string()))
+        }
+    }
+
     pub fn scan_delims(&mut self, c: u8) -> (usize, bool, bool) {
         let before_char = if self.pos == 0 {
             '\n'
@@ -518,8 +711,10 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
         closer: &'d Delimiter<'a, 'd>,
     ) -> Option<&'d Delimiter<'a, 'd>> {
         let opener_char = opener.inl.data.borrow().value.text().unwrap().as_bytes()[0];
-        let mut opener_num_chars = opener.inl.data.borrow().value.text().unwrap().len();
-        let mut closer_num_chars = closer.inl.data.borrow().value.text().unwrap().len();
+        let opener_len = opener.inl.data.borrow().value.text().unwrap().len();
+        let closer_len = closer.inl.data.borrow().value.text().unwrap().len();
+        let mut opener_num_chars = opener_len;
+        let mut closer_num_chars = closer_len;
         let use_delims = if closer_num_chars >= 2 && opener_num_chars >= 2 {
             2
         } else {
@@ -529,7 +724,7 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
         opener_num_chars -= use_delims;
         closer_num_chars -= use_delims;
 
-        if self.options.ext_strikethrough && opener_char == b'~' {
+        if opener_char == b'~' {
             opener_num_chars = 0;
             closer_num_chars = 0;
         }
@@ -559,7 +754,9 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
 
         let emph = make_inline(
             self.arena,
-            if self.options.ext_strikethrough && opener_char == b'~' {
+            if self.options.ext_subscript && opener_char == b'~' && opener_len == 1 {
+                NodeValue::Subscript
+            } else if opener_char == b'~' {
                 NodeValue::Strikethrough
             } else if self.options.ext_superscript && opener_char == b'^' {
                 NodeValue::Superscript
@@ -570,6 +767,23 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
             },
         );
 
+        {
+            let mut opener_ast = opener.inl.data.borrow_mut();
+            let mut closer_ast = closer.inl.data.borrow_mut();
+            let mut emph_ast = emph.data.borrow_mut();
+            let closer_used = closer_len - closer_num_chars;
+
+            emph_ast.start_line = opener_ast.start_line;
+            emph_ast.start_column = opener_ast.start_column + opener_num_chars;
+            emph_ast.end_line = closer_ast.start_line;
+            emph_ast.end_column = closer_ast.start_column + closer_used - 1;
+
+            if opener_num_chars > 0 {
+                opener_ast.end_column = opener_ast.start_column + opener_num_chars - 1;
+            }
+            closer_ast.start_column += closer_used;
+        }
+
         let mut tmp = opener.inl.next_sibling().unwrap();
         while !tmp.same_node(closer.inl) {
             let next = tmp.next_sibling();
@@ -643,6 +857,7 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
                 &self.input[self.pos..self.pos + matchlen - 1],
                 AutolinkType::URI,
             );
+            self.set_position(inl.first_child().unwrap(), self.pos, self.pos + matchlen - 1);
             self.pos += matchlen;
             return inl;
         }
@@ -653,6 +868,7 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
                 &self.input[self.pos..self.pos + matchlen - 1],
                 AutolinkType::Email,
             );
+            self.set_position(inl.first_child().unwrap(), self.pos, self.pos + matchlen - 1);
             self.pos += matchlen;
             return inl;
         }
@@ -731,6 +947,22 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
             }
         }
 
+        if self.options.ext_footnotes && !is_image {
+            let text = &self.input[self.brackets[brackets_len - 1].position..initial_pos - 1];
+            let name = if text.len() > 1 && text.starts_with('^') {
+                self.footnote_defs
+                    .get(&strings::normalize_reference_label(&text[1..]))
+                    .cloned()
+            } else {
+                None
+            };
+
+            if let Some(name) = name {
+                self.footnote_reference_match(name);
+                return None;
+            }
+        }
+
         let (mut lab, mut found_label) = match self.link_label() {
             Some(lab) => (lab.to_string(), true),
             None => (String::new(), false),
@@ -777,6 +1009,15 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
         );
 
         let mut brackets_len = self.brackets.len();
+        {
+            let (end_line, end_column) = self.position(self.pos - 1);
+            let inl_text = self.brackets[brackets_len - 1].inl_text.data.borrow();
+            let mut ast = inl.data.borrow_mut();
+            ast.start_line = inl_text.start_line;
+            ast.start_column = inl_text.start_column;
+            ast.end_line = end_line;
+            ast.end_column = end_column;
+        }
         self.brackets[brackets_len - 1].inl_text.insert_before(inl);
         let mut tmpch = self.brackets[brackets_len - 1].inl_text.next_sibling();
         while let Some(tmp) = tmpch {
@@ -804,6 +1045,33 @@ impl<'a, 'r, 'o, 'd> Subject<'a, 'r, 'o, 'd> {
         }
     }
 
+    pub fn footnote_reference_match(&mut self, name: String) {
+        let inl = make_inline(self.arena, NodeValue::FootnoteReference(name));
+
+        let brackets_len = self.brackets.len();
+        {
+            let (end_line, end_column) = self.position(self.pos - 1);
+            let inl_text = self.brackets[brackets_len - 1].inl_text.data.borrow();
+            let mut ast = inl.data.borrow_mut();
+            ast.start_line = inl_text.start_line;
+            ast.start_column = inl_text.start_column;
+            ast.end_line = end_line;
+            ast.end_column = end_column;
+        }
+
+        let previous_delimiter = self.brackets[brackets_len - 1].previous_delimiter;
+        self.process_emphasis(previous_delimiter);
+
+        let inl_text = self.brackets[brackets_len - 1].inl_text;
+        inl_text.insert_before(inl);
+        let mut tmpch = Some(inl_text);
+        while let Some(tmp) = tmpch {
+            tmpch = tmp.next_sibling();
+            tmp.detach();
+        }
+        self.brackets.pop();
+    }
+
     pub fn link_label(&mut self) -> Option<&str> {
         let startpos = self.pos;
 
@@ -895,17 +1163,7 @@ pub fn manual_scan_link_url(input: &str) -> Option<usize> {
 }
 
 pub fn make_inline<'a>(arena: &'a Arena<AstNode<'a>>, value: NodeValue) -> &'a AstNode<'a> {
-    let ast = Ast {
-        value: value,
-        content: String::new(),
-        start_line: 0,
-        start_column: 0,
-        end_line: 0,
-        end_column: 0,
-        open: false,
-        last_line_blank: false,
-    };
-    arena.alloc(Node::new(RefCell::new(ast)))
+    AstNode::new_in(arena, value)
 }
 
 fn make_autolink<'a>(
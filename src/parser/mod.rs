@@ -6,15 +6,19 @@ mod inlines;
 use arena_tree::Node;
 use ctype::{isspace, isdigit};
 use entity;
+use error::Error;
 use nodes;
 use nodes::{NodeValue, Ast, NodeCodeBlock, NodeHeading, NodeList, ListType, ListDelimType,
-            NodeHtmlBlock, make_block, AstNode};
+            NodeHtmlBlock, NodeDescriptionList, NodeDescriptionDetails, make_block, AstNode};
 use regex::Regex;
 use scanners;
 use std::cell::RefCell;
 use std::cmp::min;
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
 use std::mem;
+use std::str;
 use strings;
 use typed_arena::Arena;
 
@@ -29,24 +33,246 @@ pub fn parse_document<'a>(
     buffer: &str,
     options: &ComrakOptions,
 ) -> &'a AstNode<'a> {
-    let root: &'a AstNode<'a> = arena.alloc(Node::new(RefCell::new(Ast {
+    let root = make_document(arena);
+    let mut parser = Parser::new(arena, root, options);
+    parser.feed(buffer, true);
+    parser.finish()
+}
+
+fn make_document<'a>(arena: &'a Arena<AstNode<'a>>) -> &'a AstNode<'a> {
+    arena.alloc(Node::new(RefCell::new(Ast {
         value: NodeValue::Document,
         content: String::new(),
+        line_offsets: Vec::new(),
         start_line: 0,
         start_column: 0,
         end_line: 0,
         end_column: 0,
         open: true,
         last_line_blank: false,
-    })));
-    let mut parser = Parser::new(arena, root, options);
-    parser.feed(buffer, true);
-    parser.finish()
+    })))
+}
+
+/// A parser which accepts a Markdown document incrementally, in chunks of any size.
+///
+/// Chunks may split lines, line endings, and (when fed as bytes) UTF-8 sequences anywhere; the
+/// resulting AST is the same as if the concatenated input were passed to `parse_document`.
+/// Invalid UTF-8 is replaced with U+FFFD by `feed_bytes`, and reported as an error by
+/// `read_from`.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{format_html, ComrakOptions, StreamingParser};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let mut parser = StreamingParser::new(&arena, &options);
+///
+/// parser.feed("# Hello, ");
+/// parser.feed_bytes(b"\xe4\xb8");
+/// parser.feed_bytes(b"\x96\xe7\x95\x8c\r");
+/// parser.feed("\nHow *are* you?");
+/// let root = parser.finish();
+///
+/// assert_eq!(format_html(root, &options),
+///            "<h1>Hello, 世界</h1>\n<p>How <em>are</em> you?</p>\n");
+/// # }
+/// ```
+pub struct StreamingParser<'a, 'o> {
+    parser: Parser<'a, 'o>,
+    partial_char: Vec<u8>,
+    front_matter: Option<String>,
+}
+
+impl<'a, 'o> StreamingParser<'a, 'o> {
+    /// Creates a parser which allocates the document's nodes in `arena`.
+    pub fn new(arena: &'a Arena<AstNode<'a>>, options: &'o ComrakOptions) -> Self {
+        StreamingParser {
+            parser: Parser::new(arena, make_document(arena), options),
+            partial_char: vec![],
+            front_matter: options.front_matter_delimiter.as_ref().map(|_| String::new()),
+        }
+    }
+
+    /// Parses the next chunk of the document.
+    pub fn feed(&mut self, chunk: &str) {
+        if !self.partial_char.is_empty() {
+            let partial = mem::replace(&mut self.partial_char, vec![]);
+            self.feed_str(&String::from_utf8_lossy(&partial));
+        }
+        self.feed_str(chunk);
+    }
+
+    /// Parses the next chunk of the document, given as UTF-8 bytes.
+    ///
+    /// A multi-byte character split at the end of `chunk` is held back until the next call.
+    pub fn feed_bytes(&mut self, chunk: &[u8]) {
+        let mut owned;
+        let mut bytes = chunk;
+        if !self.partial_char.is_empty() {
+            owned = mem::replace(&mut self.partial_char, vec![]);
+            owned.extend_from_slice(chunk);
+            bytes = &owned;
+        }
+
+        loop {
+            match str::from_utf8(bytes) {
+                Ok(s) => {
+                    self.feed_str(s);
+                    return;
+                }
+                Err(e) => {
+                    let (valid, rest) = bytes.split_at(e.valid_up_to());
+                    self.feed_str(str::from_utf8(valid).unwrap());
+                    match e.error_len() {
+                        Some(len) => {
+                            self.feed_str("\u{fffd}");
+                            bytes = &rest[len..];
+                        }
+                        None => {
+                            self.partial_char.extend_from_slice(rest);
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads and parses the next part of the document from `input` until it is exhausted.
+    ///
+    /// Unlike `feed_bytes`, the input must be valid UTF-8, and must not end partway through a
+    /// character; otherwise an error giving the offset of the invalid bytes within `input` is
+    /// returned.  Reading stops at the first error; whatever was read before it is still parsed.
+    ///
+    /// ```
+    /// # extern crate comrak;
+    /// # extern crate typed_arena;
+    /// # use comrak::{format_html, ComrakOptions, Error, StreamingParser};
+    /// # fn main() {
+    /// let arena = typed_arena::Arena::new();
+    /// let options = ComrakOptions::default();
+    /// let mut parser = StreamingParser::new(&arena, &options);
+    ///
+    /// parser.read_from(&b"*Hello*\n"[..]).unwrap();
+    /// match parser.read_from(&b"caf\xe9\n"[..]) {
+    ///     Err(Error::InvalidUtf8 { offset }) => assert_eq!(offset, 3),
+    ///     _ => unreachable!(),
+    /// }
+    /// # }
+    /// ```
+    pub fn read_from<R: Read>(&mut self, mut input: R) -> Result<(), Error> {
+        if !self.partial_char.is_empty() {
+            let partial = mem::replace(&mut self.partial_char, vec![]);
+            self.feed_str(&String::from_utf8_lossy(&partial));
+        }
+
+        let mut buf = [0; 8192];
+        let mut partial = vec![];
+        let mut offset = 0;
+        loop {
+            let n = match input.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::Io(e)),
+            };
+
+            partial.extend_from_slice(&buf[..n]);
+            let valid_up_to = match str::from_utf8(&partial) {
+                Ok(_) => partial.len(),
+                Err(e) => {
+                    if e.error_len().is_some() {
+                        let valid_up_to = e.valid_up_to();
+                        self.feed_str(str::from_utf8(&partial[..valid_up_to]).unwrap());
+                        return Err(Error::InvalidUtf8 { offset: offset + valid_up_to });
+                    }
+                    e.valid_up_to()
+                }
+            };
+
+            self.feed_str(str::from_utf8(&partial[..valid_up_to]).unwrap());
+            offset += valid_up_to;
+            partial.drain(..valid_up_to);
+        }
+
+        if partial.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidUtf8 { offset: offset })
+        }
+    }
+
+    /// Finishes parsing the document, returning its root node.
+    pub fn finish(mut self) -> &'a AstNode<'a> {
+        if !self.partial_char.is_empty() {
+            let partial = mem::replace(&mut self.partial_char, vec![]);
+            self.feed_str(&String::from_utf8_lossy(&partial));
+        }
+        if let Some(pending) = self.front_matter.take() {
+            self.parser.feed(&pending, false);
+        }
+        self.parser.finish()
+    }
+
+    fn feed_str(&mut self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+
+        // Front matter is only recognised at the start of the first chunk the parser sees, so
+        // input is held back until it's known whether the document begins with front matter.
+        if let Some(mut pending) = self.front_matter.take() {
+            pending += s;
+            let options = self.parser.options;
+            if front_matter_resolved(&pending, options.front_matter_delimiter.as_ref().unwrap()) {
+                self.parser.feed(&pending, false);
+            } else {
+                self.front_matter = Some(pending);
+            }
+            return;
+        }
+
+        self.parser.feed(s, false);
+    }
+}
+
+impl<'a, 'o> fmt::Debug for StreamingParser<'a, 'o> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StreamingParser")
+            .field("line_number", &self.parser.line_number)
+            .field("options", self.parser.options)
+            .finish()
+    }
+}
+
+fn front_matter_resolved(pending: &str, delimiter: &str) -> bool {
+    let first_line = match pending.find(&['\r', '\n'][..]) {
+        Some(eol) => &pending[..eol],
+        None => return !delimiter.starts_with(pending),
+    };
+    if first_line != delimiter {
+        return true;
+    }
+
+    // Once a line has started beyond the limit without the block being closed, it can't be
+    // front matter, so there's no need to keep buffering.
+    match strings::front_matter_len(pending, delimiter) {
+        Some(len) => !pending[len..].trim().is_empty(),
+        None => {
+            match pending.rfind(&['\r', '\n'][..]) {
+                Some(eol) => eol + 1 >= strings::MAX_FRONT_MATTER_LENGTH,
+                None => false,
+            }
+        }
+    }
 }
 
 pub struct Parser<'a, 'o> {
     arena: &'a Arena<AstNode<'a>>,
     refmap: HashMap<String, Reference>,
+    footnote_defs: HashMap<String, String>,
     root: &'a AstNode<'a>,
     current: &'a AstNode<'a>,
     line_number: u32,
@@ -59,11 +285,12 @@ pub struct Parser<'a, 'o> {
     partially_consumed_tab: bool,
     last_line_length: usize,
     linebuf: String,
+    curline: String,
     last_buffer_ended_with_cr: bool,
     options: &'o ComrakOptions,
 }
 
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone)]
 /// Options for both parser and formatter functions.
 pub struct ComrakOptions {
     /// [Soft line breaks](http://spec.commonmark.org/0.27/#soft-line-breaks) in the input
@@ -95,7 +322,45 @@ pub struct ComrakOptions {
     /// ```
     pub github_pre_lang: bool,
 
-    /// The wrap column when outputting CommonMark.
+    /// Include source position attributes in HTML and XML output.
+    ///
+    /// Each block element is given a `data-sourcepos` attribute of the form
+    /// `start_line:start_column-end_line:end_column`, as cmark's `--sourcepos` does.  In XML
+    /// output, every node with a known position is given a `sourcepos` attribute instead.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.sourcepos = true;
+    /// assert_eq!(markdown_to_html("Hello *world*.\n\n> Hi.\n", &options),
+    ///            "<p data-sourcepos=\"1:1-1:14\">Hello <em>world</em>.</p>\n\
+    ///             <blockquote data-sourcepos=\"3:1-3:5\">\n\
+    ///             <p data-sourcepos=\"3:3-3:5\">Hi.</p>\n\
+    ///             </blockquote>\n");
+    /// ```
+    pub sourcepos: bool,
+
+    /// Render task list checkboxes in HTML output as enabled, so they can be toggled in the
+    /// browser.
+    ///
+    /// Each checkbox is given a `data-task-index` attribute numbering the task items in document
+    /// order from zero, and a `data-task-line` attribute giving the line its marker is on.  Pass
+    /// the index to `toggle_task_item` to update the source text when a checkbox is clicked.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_tasklist = true;
+    /// options.tasklist_interactive = true;
+    /// assert_eq!(markdown_to_html("* [x] Done\n\n  More.\n* [ ] Not done\n", &options),
+    ///            "<ul>\n<li>\n<p><input type=\"checkbox\" data-task-index=\"0\" \
+    ///             data-task-line=\"1\" checked=\"\" /> Done</p>\n<p>More.</p>\n</li>\n\
+    ///             <li>\n<p><input type=\"checkbox\" data-task-index=\"1\" \
+    ///             data-task-line=\"4\" /> Not done</p>\n</li>\n</ul>\n");
+    /// ```
+    pub tasklist_interactive: bool,
+
+    /// The wrap column when outputting CommonMark, plain text, man pages or LaTeX.
     ///
     /// ```
     /// # extern crate typed_arena;
@@ -115,6 +380,71 @@ pub struct ComrakOptions {
     /// ```
     pub width: usize,
 
+    /// Emit a complete document, with a preamble, when outputting LaTeX.  Otherwise only the body
+    /// is emitted, for inclusion in another document.
+    ///
+    /// ```
+    /// # extern crate typed_arena;
+    /// # extern crate comrak;
+    /// # use comrak::{parse_document, ComrakOptions, format_latex};
+    /// # fn main() {
+    /// # let arena = typed_arena::Arena::new();
+    /// let mut options = ComrakOptions::default();
+    /// let node = parse_document(&arena, "Hello.\n", &options);
+    /// assert_eq!(format_latex(node, &options), "Hello.\n");
+    ///
+    /// options.latex_standalone = true;
+    /// let latex = format_latex(node, &options);
+    /// assert!(latex.starts_with("\\documentclass{article}\n"));
+    /// assert!(latex.ends_with("\\begin{document}\n\nHello.\n\n\\end{document}\n"));
+    /// # }
+    /// ```
+    pub latex_standalone: bool,
+
+    /// Disable rendering of raw HTML and potentially dangerous links.
+    ///
+    /// Raw HTML blocks and inlines are replaced with an HTML comment, and link and image
+    /// destinations using the `javascript:`, `vbscript:` or `file:` schemes, or a `data:` URL
+    /// which is not a PNG, GIF, JPEG or WebP image, are emitted as empty.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// let input = "<script>\nalert('xyz');\n</script>\n\n\
+    ///              Possibly <marquee>annoying</marquee>.\n\n\
+    ///              [Dangerous](javascript:alert(document.cookie)).\n\n\
+    ///              [Safe](http://commonmark.org).\n";
+    ///
+    /// assert_eq!(markdown_to_html(input, &options),
+    ///            "<script>\nalert(\'xyz\');\n</script>\n\
+    ///             <p>Possibly <marquee>annoying</marquee>.</p>\n\
+    ///             <p><a href=\"javascript:alert(document.cookie)\">Dangerous</a>.</p>\n\
+    ///             <p><a href=\"http://commonmark.org\">Safe</a>.</p>\n");
+    ///
+    /// options.safe = true;
+    /// assert_eq!(markdown_to_html(input, &options),
+    ///            "<!-- raw HTML omitted -->\n\
+    ///             <p>Possibly <!-- raw HTML omitted -->annoying<!-- raw HTML omitted -->.</p>\n\
+    ///             <p><a href=\"\">Dangerous</a>.</p>\n\
+    ///             <p><a href=\"http://commonmark.org\">Safe</a>.</p>\n");
+    /// ```
+    pub safe: bool,
+
+    /// Punctuation (quotes, full-stops and hyphens) are converted into "smart" punctuation, as
+    /// cmark's `--smart` does.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// assert_eq!(markdown_to_html("'Hello,' \"world\" ...", &options),
+    ///            "<p>'Hello,' &quot;world&quot; ...</p>\n");
+    ///
+    /// options.smart = true;
+    /// assert_eq!(markdown_to_html("'Hello,' \"world\" ...", &options),
+    ///            "<p>‘Hello,’ “world” …</p>\n");
+    /// ```
+    pub smart: bool,
+
     /// Enables the
     /// [strikethrough extension](https://github.github.com/gfm/#strikethrough-extension-)
     /// from the GFM spec.
@@ -171,7 +501,7 @@ pub struct ComrakOptions {
     /// from the GFM spec.
     ///
     /// Note that the spec does not define the precise output, so only the bare essentials are
-    /// rendered.
+    /// rendered.  Each item's checkbox is parsed as a `NodeValue::TaskItem`.
     ///
     /// ```
     /// # use comrak::{markdown_to_html, ComrakOptions};
@@ -193,6 +523,97 @@ pub struct ComrakOptions {
     ///            "<p>e = mc<sup>2</sup>.</p>\n");
     /// ```
     pub ext_superscript: bool,
+
+    /// Enables the subscript Comrak extension.  Text between single tildes becomes subscript; if
+    /// `ext_strikethrough` is also set, text between double tildes is struck through as before.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_subscript = true;
+    /// options.ext_strikethrough = true;
+    /// assert_eq!(markdown_to_html("H~2~O is ~~not~~ wet.\n", &options),
+    ///            "<p>H<sub>2</sub>O is <del>not</del> wet.</p>\n");
+    /// ```
+    pub ext_subscript: bool,
+
+    /// Enables the footnotes extension per `cmark-gfm`.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_footnotes = true;
+    /// assert_eq!(markdown_to_html("Hi[^x].\n\n[^x]: A greeting.\n", &options),
+    ///            "<p>Hi<sup class=\"footnote-ref\"><a href=\"#fn1\" id=\"fnref1\">1</a></sup>.</p>\n\
+    ///             <section class=\"footnotes\">\n<ol>\n<li id=\"fn1\">\n\
+    ///             <p>A greeting. <a href=\"#fnref1\" class=\"footnote-backref\">↩</a></p>\n\
+    ///             </li>\n</ol>\n</section>\n");
+    /// ```
+    pub ext_footnotes: bool,
+
+    /// Enables the description lists Comrak extension.  A paragraph followed by a line starting
+    /// with `: ` becomes a term, with the rest of the line starting its details; further lines of
+    /// the details are indented by two spaces.  If a blank line separates any details from their
+    /// term, the list is loose.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_description_lists = true;
+    /// assert_eq!(markdown_to_html("Term\n: Details.\n\nAnother term\n: More.\n", &options),
+    ///            "<dl>\n<dt>Term</dt>\n<dd>Details.</dd>\n\
+    ///             <dt>Another term</dt>\n<dd>More.</dd>\n</dl>\n");
+    ///
+    /// assert_eq!(markdown_to_html("Term\n\n: Details.\n", &options),
+    ///            "<dl>\n<dt>Term</dt>\n<dd>\n<p>Details.</p>\n</dd>\n</dl>\n");
+    /// ```
+    pub ext_description_lists: bool,
+
+    /// Enables the math Comrak extension.  Text between `$` delimiters becomes inline math, text
+    /// between `$$` delimiters becomes display math, and fenced code blocks with the info string
+    /// `math` become math blocks; none of them are parsed as Markdown.  An opening `$` must not
+    /// be followed by whitespace, and a closing `$` must not follow whitespace nor be followed by
+    /// a digit, so prices like `$5 and $10` are left alone.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_math = true;
+    /// assert_eq!(markdown_to_html("Let $x_1 * x_2$ be:\n\n$$x_1 = 2$$\n", &options),
+    ///            "<p>Let <span class=\"math math-inline\">x_1 * x_2</span> be:</p>\n\
+    ///             <p><span class=\"math math-display\">x_1 = 2</span></p>\n");
+    ///
+    /// assert_eq!(markdown_to_html("``` math\na < b\n```\n", &options),
+    ///            "<div class=\"math math-display\">a &lt; b\n</div>\n");
+    /// ```
+    pub ext_math: bool,
+
+    /// Enables the header IDs Comrak extension.  Each heading is given an anchor with an ID
+    /// generated from its text content as GitHub does, prefixed with the given string.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.ext_header_ids = Some("user-content-".to_string());
+    /// assert_eq!(markdown_to_html("# README\n", &options),
+    ///            "<h1><a href=\"#readme\" aria-hidden=\"true\" class=\"anchor\" \
+    ///             id=\"user-content-readme\"></a>README</h1>\n");
+    /// ```
+    pub ext_header_ids: Option<String>,
+
+    /// Enables front matter: a block at the very start of the document which begins and ends
+    /// with a line consisting of the given delimiter is captured verbatim as a `FrontMatter`
+    /// node, rather than being parsed as Markdown.  The closing delimiter must begin within the
+    /// first 64 KiB of the document.
+    ///
+    /// ```
+    /// # use comrak::{markdown_to_html, ComrakOptions};
+    /// let mut options = ComrakOptions::default();
+    /// options.front_matter_delimiter = Some("---".to_string());
+    /// assert_eq!(markdown_to_html("---\ntitle: Hello\n---\n\n# Hello\n", &options),
+    ///            "<h1>Hello</h1>\n");
+    /// ```
+    pub front_matter_delimiter: Option<String>,
 }
 
 
@@ -202,6 +623,71 @@ pub struct Reference {
     pub title: String,
 }
 
+/// Maps byte offsets in a text node's literal back to the source columns they came from.
+///
+/// A text node is merged from pieces which each cover a span of the source.  Most pieces are the
+/// source verbatim, but entities, backslash escapes and smart punctuation decode to text of a
+/// different length, and any offset within one of those maps to its whole span.
+pub struct TextColumns {
+    pieces: Vec<(usize, usize, usize, usize)>,
+    skipped: usize,
+}
+
+impl TextColumns {
+    fn new() -> Self {
+        TextColumns {
+            pieces: vec![],
+            skipped: 0,
+        }
+    }
+
+    /// Adds a piece of `len` bytes at `offset` in the literal, from the given source columns.
+    fn push(&mut self, offset: usize, len: usize, start_column: usize, end_column: usize) {
+        self.pieces.push((offset, len, start_column, end_column));
+    }
+
+    /// Drops the first `n` bytes of the literal, so that offsets are counted from after them.
+    fn skip(&mut self, n: usize) {
+        self.skipped += n;
+    }
+
+    fn piece(&self, offset: usize) -> (usize, usize, usize, usize, bool) {
+        let offset = offset + self.skipped;
+        let &(start, len, start_column, end_column) = self.pieces
+            .iter()
+            .rev()
+            .find(|p| p.0 <= offset)
+            .unwrap_or(&self.pieces[0]);
+        (offset - start, len, start_column, end_column, end_column + 1 == start_column + len)
+    }
+
+    /// The column at which the byte at `offset` starts.
+    pub fn start(&self, offset: usize) -> usize {
+        match self.piece(offset) {
+            (i, len, _, end_column, _) if i >= len => end_column + 1,
+            (i, _, start_column, _, true) => start_column + i,
+            (_, _, start_column, _, false) => start_column,
+        }
+    }
+
+    /// The column at which the byte at `offset` ends.
+    pub fn end(&self, offset: usize) -> usize {
+        match self.piece(offset) {
+            (i, _, start_column, _, true) => start_column + i,
+            (_, _, _, end_column, _) => end_column,
+        }
+    }
+
+    /// The column at which the text before `offset` ends.
+    pub fn end_before(&self, offset: usize) -> usize {
+        if offset == 0 {
+            self.start(0) - 1
+        } else {
+            self.end(offset - 1)
+        }
+    }
+}
+
 impl<'a, 'o> Parser<'a, 'o> {
     pub fn new(
         arena: &'a Arena<AstNode<'a>>,
@@ -211,6 +697,7 @@ impl<'a, 'o> Parser<'a, 'o> {
         Parser {
             arena: arena,
             refmap: HashMap::new(),
+            footnote_defs: HashMap::new(),
             root: root,
             current: root,
             line_number: 0,
@@ -223,17 +710,27 @@ impl<'a, 'o> Parser<'a, 'o> {
             partially_consumed_tab: false,
             last_line_length: 0,
             linebuf: String::with_capacity(80),
+            curline: String::with_capacity(80),
             last_buffer_ended_with_cr: false,
             options: options,
         }
     }
 
-    pub fn feed(&mut self, s: &str, eof: bool) {
+    pub fn feed(&mut self, mut s: &str, eof: bool) {
+        if self.line_number == 0 && self.linebuf.is_empty() {
+            if let Some(ref delimiter) = self.options.front_matter_delimiter {
+                if let Some(len) = strings::front_matter_len(s, delimiter) {
+                    self.add_front_matter(&s[..len]);
+                    s = &s[len..];
+                }
+            }
+        }
+
         let mut i = 0;
         let buffer = s.as_bytes();
         let sz = buffer.len();
 
-        if self.last_buffer_ended_with_cr && buffer[i] == b'\n' {
+        if self.last_buffer_ended_with_cr && i < sz && buffer[i] == b'\n' {
             i += 1;
         }
         self.last_buffer_ended_with_cr = false;
@@ -287,6 +784,22 @@ impl<'a, 'o> Parser<'a, 'o> {
         }
     }
 
+    fn add_front_matter(&mut self, front_matter: &str) {
+        let lines = front_matter
+            .bytes()
+            .enumerate()
+            .filter(|&(i, c)| {
+                c == b'\n' || (c == b'\r' && front_matter.as_bytes().get(i + 1) != Some(&b'\n'))
+            })
+            .count() as u32;
+
+        let mut ast = make_block(NodeValue::FrontMatter(front_matter.to_string()), 1, 1);
+        ast.open = false;
+        ast.end_line = lines;
+        self.root.append(self.arena.alloc(Node::new(RefCell::new(ast))));
+        self.line_number = lines;
+    }
+
     fn find_first_nonspace(&mut self, line: &str) {
         self.first_nonspace = self.offset;
         self.first_nonspace_column = self.column;
@@ -330,6 +843,8 @@ impl<'a, 'o> Parser<'a, 'o> {
                 line
             };
 
+        self.curline.clear();
+        self.curline += line;
         self.offset = 0;
         self.column = 0;
         self.blank = false;
@@ -359,6 +874,8 @@ impl<'a, 'o> Parser<'a, 'o> {
         if self.last_line_length > 0 && line.as_bytes()[self.last_line_length - 1] == b'\r' {
             self.last_line_length -= 1;
         }
+
+        self.curline.clear();
     }
 
     fn check_open_blocks(&mut self, line: &str, all_matched: &mut bool) -> Option<&'a AstNode<'a>> {
@@ -416,6 +933,16 @@ impl<'a, 'o> Parser<'a, 'o> {
                         return (false, container, should_continue);
                     }
                 }
+                NodeValue::FootnoteDefinition(..) => {
+                    if !self.parse_footnote_definition_block_prefix(line) {
+                        return (false, container, should_continue);
+                    }
+                }
+                NodeValue::DescriptionDetails(ref ndd) => {
+                    if !self.parse_description_details_prefix(line, container, ndd) {
+                        return (false, container, should_continue);
+                    }
+                }
                 NodeValue::Table(..) => {
                     if !table::matches(&line[self.first_nonspace..]) {
                         return (false, container, should_continue);
@@ -550,6 +1077,7 @@ impl<'a, 'o> Parser<'a, 'o> {
                     },
                     setext: true,
                 });
+                self.set_end_of_curline(*container);
                 let adv = line.len() - 1 - self.offset;
                 self.advance_offset(line, adv, false);
             } else if !indented &&
@@ -565,8 +1093,36 @@ impl<'a, 'o> Parser<'a, 'o> {
             {
                 let offset = self.first_nonspace + 1;
                 *container = self.add_child(*container, NodeValue::ThematicBreak, offset);
+                self.set_end_of_curline(*container);
                 let adv = line.len() - 1 - self.offset;
                 self.advance_offset(line, adv, false);
+            } else if !indented && self.options.ext_footnotes &&
+                       unwrap_into(
+                    scanners::footnote_definition(&line[self.first_nonspace..]),
+                    &mut matched,
+                )
+            {
+                let mut name = line[self.first_nonspace + 2..self.first_nonspace + matched]
+                    .to_string();
+                let name_len = name.rfind("]:").unwrap();
+                name.truncate(name_len);
+                self.footnote_defs
+                    .entry(strings::normalize_reference_label(&name))
+                    .or_insert_with(|| name.clone());
+
+                let offset = self.first_nonspace + 1;
+                *container =
+                    self.add_child(*container, NodeValue::FootnoteDefinition(name), offset);
+                let adv = self.first_nonspace + matched - self.offset;
+                self.advance_offset(line, adv, false);
+            } else if !indented && self.options.ext_description_lists &&
+                       line.as_bytes()[self.first_nonspace] == b':' &&
+                       strings::is_space_or_tab(line.as_bytes()[self.first_nonspace + 1]) &&
+                       self.open_description_details(container)
+            {
+                let offset = self.first_nonspace + 1 - self.offset;
+                self.advance_offset(line, offset, false);
+                self.advance_offset(line, 1, true);
             } else if (!indented ||
                            match container.data.borrow().value {
                                NodeValue::List(..) => true,
@@ -724,6 +1280,37 @@ impl<'a, 'o> Parser<'a, 'o> {
         }
     }
 
+    fn parse_footnote_definition_block_prefix(&mut self, line: &str) -> bool {
+        if self.indent >= CODE_INDENT {
+            self.advance_offset(line, CODE_INDENT, true);
+            true
+        } else if self.blank {
+            let offset = self.first_nonspace - self.offset;
+            self.advance_offset(line, offset, false);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_description_details_prefix(
+        &mut self,
+        line: &str,
+        container: &'a AstNode<'a>,
+        ndd: &NodeDescriptionDetails,
+    ) -> bool {
+        if self.indent >= ndd.marker_offset + ndd.padding {
+            self.advance_offset(line, ndd.marker_offset + ndd.padding, true);
+            true
+        } else if self.blank && container.first_child().is_some() {
+            let offset = self.first_nonspace - self.offset;
+            self.advance_offset(line, offset, false);
+            true
+        } else {
+            false
+        }
+    }
+
     fn parse_code_block_prefix(
         &mut self,
         line: &str,
@@ -804,6 +1391,96 @@ impl<'a, 'o> Parser<'a, 'o> {
         node
     }
 
+    /// Opens description details for the line being processed, if it follows a term or other
+    /// details.  The paragraph before the line becomes the term, joining the description list
+    /// immediately before it, if any.
+    fn open_description_details(&mut self, container: &mut &'a AstNode<'a>) -> bool {
+        let item = match container.data.borrow().value {
+            NodeValue::DescriptionItem => Some(*container),
+            _ => None,
+        };
+
+        let item = match item {
+            Some(item) => item,
+            None => {
+                let paragraph = match container.data.borrow().value {
+                    NodeValue::Paragraph => Some(*container),
+                    _ => {
+                        container.last_child().and_then(|last_child| {
+                            match last_child.data.borrow().value {
+                                NodeValue::Paragraph => Some(last_child),
+                                _ => None,
+                            }
+                        })
+                    }
+                };
+
+                let paragraph = match paragraph {
+                    Some(paragraph) => paragraph,
+                    None => return false,
+                };
+
+                if paragraph.data.borrow().open {
+                    *container = self.finalize(paragraph).unwrap();
+                    // The paragraph may have consisted only of link reference definitions.
+                    if paragraph.parent().is_none() {
+                        return false;
+                    }
+                }
+
+                let (start_line, start_column, end_line, end_column) = {
+                    let ast = paragraph.data.borrow();
+                    (ast.start_line, ast.start_column, ast.end_line, ast.end_column)
+                };
+
+                let previous_list = paragraph.previous_sibling().and_then(|previous| {
+                    match previous.data.borrow().value {
+                        NodeValue::DescriptionList(..) => Some(previous),
+                        _ => None,
+                    }
+                });
+                let list = match previous_list {
+                    Some(list) => {
+                        list.data.borrow_mut().open = true;
+                        list
+                    }
+                    None => {
+                        let list = self.add_child(
+                            *container,
+                            NodeValue::DescriptionList(NodeDescriptionList::default()),
+                            start_column,
+                        );
+                        list.data.borrow_mut().start_line = start_line;
+                        list
+                    }
+                };
+
+                let item = self.add_child(list, NodeValue::DescriptionItem, start_column);
+                let term = self.add_child(item, NodeValue::DescriptionTerm, start_column);
+                for node in &[item, term] {
+                    node.data.borrow_mut().start_line = start_line;
+                }
+                {
+                    let mut ast = term.data.borrow_mut();
+                    ast.end_line = end_line;
+                    ast.end_column = end_column;
+                    ast.open = false;
+                }
+                paragraph.detach();
+                term.append(paragraph);
+                item
+            }
+        };
+
+        let ndd = NodeDescriptionDetails {
+            marker_offset: self.indent,
+            padding: 2,
+        };
+        let offset = self.first_nonspace + 1;
+        *container = self.add_child(item, NodeValue::DescriptionDetails(ndd), offset);
+        true
+    }
+
     fn add_text_to_container(
         &mut self,
         mut container: &'a AstNode<'a>,
@@ -914,6 +1591,9 @@ impl<'a, 'o> Parser<'a, 'o> {
             }
         }
         if self.offset < line.len() {
+            if ast.value.contains_inlines() {
+                ast.line_offsets.push(self.offset);
+            }
             ast.content += &line[self.offset..];
         }
     }
@@ -925,10 +1605,31 @@ impl<'a, 'o> Parser<'a, 'o> {
         }
 
         self.finalize_document();
+        fix_zero_end_columns(self.root);
         self.postprocess_text_nodes(self.root);
+        if self.options.ext_footnotes {
+            self.process_footnotes();
+        }
         self.root
     }
 
+    fn curline_end_column(&self) -> usize {
+        let mut end_column = self.curline.len();
+        if end_column > 0 && self.curline.as_bytes()[end_column - 1] == b'\n' {
+            end_column -= 1;
+        }
+        if end_column > 0 && self.curline.as_bytes()[end_column - 1] == b'\r' {
+            end_column -= 1;
+        }
+        end_column
+    }
+
+    fn set_end_of_curline(&self, node: &'a AstNode<'a>) {
+        let mut ast = node.data.borrow_mut();
+        ast.end_line = self.line_number;
+        ast.end_column = self.curline_end_column();
+    }
+
     fn finalize_document(&mut self) {
         while !self.current.same_node(self.root) {
             self.current = self.finalize(self.current).unwrap();
@@ -950,24 +1651,24 @@ impl<'a, 'o> Parser<'a, 'o> {
         assert!(ast.open);
         ast.open = false;
 
-        if !self.linebuf.is_empty() {
+        if self.curline.is_empty() {
             ast.end_line = self.line_number;
             ast.end_column = self.last_line_length;
         } else if match ast.value {
                    NodeValue::Document => true,
                    NodeValue::CodeBlock(ref ncb) => ncb.fenced,
-                   NodeValue::Heading(ref nh) => nh.setext,
                    _ => false,
                }
         {
             ast.end_line = self.line_number;
-            ast.end_column = self.linebuf.len();
-            if ast.end_column > 0 && self.linebuf.as_bytes()[ast.end_column - 1] == b'\n' {
-                ast.end_column -= 1;
-            }
-            if ast.end_column > 0 && self.linebuf.as_bytes()[ast.end_column - 1] == b'\r' {
-                ast.end_column -= 1;
-            }
+            ast.end_column = self.curline_end_column();
+        } else if match ast.value {
+                   NodeValue::ThematicBreak => true,
+                   NodeValue::Heading(ref nh) => nh.setext,
+                   _ => false,
+               }
+        {
+            // The end position was set when the block was opened.
         } else {
             ast.end_line = self.line_number - 1;
             ast.end_column = self.last_line_length;
@@ -983,6 +1684,13 @@ impl<'a, 'o> Parser<'a, 'o> {
                 while !content.is_empty() && content.as_bytes()[0] == b'[' &&
                     unwrap_into(self.parse_reference_inline(content), &mut pos)
                 {
+                    let lines = content[..pos].bytes().filter(|&c| c == b'\n').count();
+                    ast.line_offsets.drain(..min(lines, ast.line_offsets.len()));
+                    ast.start_line += lines as u32;
+                    if let Some(&line_offset) = ast.line_offsets.first() {
+                        ast.start_column = line_offset + 1;
+                    }
+
                     while pos > 0 {
                         pos -= content.remove(0).len_utf8();
                     }
@@ -1028,6 +1736,13 @@ impl<'a, 'o> Parser<'a, 'o> {
                 mem::swap(&mut nhb.literal, content);
                 content.clear();
             }
+            NodeValue::DescriptionList(ref mut ndl) => {
+                ndl.tight = !node.children().any(|item| {
+                    item.children().any(|child| {
+                        ends_before_blank_line(child) || child.children().any(ends_before_blank_line)
+                    })
+                });
+            }
             NodeValue::List(ref mut nl) => {
                 nl.tight = true;
                 let mut ch = node.first_child();
@@ -1059,6 +1774,15 @@ impl<'a, 'o> Parser<'a, 'o> {
             _ => (),
         }
 
+        let math_block = match ast.value {
+            NodeValue::CodeBlock(ref ncb) if self.options.ext_math && ncb.fenced &&
+                ncb.info.split_whitespace().next() == Some("math") => Some(ncb.clone()),
+            _ => None,
+        };
+        if let Some(ncb) = math_block {
+            ast.value = NodeValue::MathBlock(ncb);
+        }
+
         parent
     }
 
@@ -1078,11 +1802,15 @@ impl<'a, 'o> Parser<'a, 'o> {
 
     fn parse_inlines(&mut self, node: &'a AstNode<'a>) {
         let delimiter_arena = Arena::new();
+        let ast = node.data.borrow();
         let mut subj = inlines::Subject::new(
             self.arena,
             self.options,
-            &node.data.borrow().content,
+            &ast.content,
+            ast.start_line,
+            &ast.line_offsets,
             &mut self.refmap,
+            &self.footnote_defs,
             &delimiter_arena,
         );
 
@@ -1095,36 +1823,82 @@ impl<'a, 'o> Parser<'a, 'o> {
         while subj.pop_bracket() {}
     }
 
+    fn process_footnotes(&mut self) {
+        let mut definitions = vec![];
+        let mut references = vec![];
+
+        for node in self.root.descendants() {
+            match node.data.borrow().value {
+                NodeValue::FootnoteDefinition(..) => definitions.push(node),
+                NodeValue::FootnoteReference(ref name) => {
+                    if !references.contains(name) {
+                        references.push(name.clone());
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let mut by_name = HashMap::new();
+        for node in definitions {
+            node.detach();
+            if let NodeValue::FootnoteDefinition(ref name) = node.data.borrow().value {
+                by_name.entry(strings::normalize_reference_label(name)).or_insert(node);
+            }
+        }
+
+        for name in &references {
+            if let Some(node) = by_name.get(&strings::normalize_reference_label(name)) {
+                self.root.append(node);
+            }
+        }
+    }
+
     fn postprocess_text_nodes(&mut self, node: &'a AstNode<'a>) {
         let mut nch = node.first_child();
 
         while let Some(n) = nch {
             let mut this_bracket = false;
-            loop {
-                match n.data.borrow_mut().value {
+            let mut last = n;
+            {
+                let mut ast = n.data.borrow_mut();
+                let ast = &mut *ast;
+                match ast.value {
                     NodeValue::Text(ref mut root) => {
-                        let ns = match n.next_sibling() {
-                            Some(ns) => ns,
-                            _ => {
-                                self.postprocess_text_node(n, root);
-                                break;
-                            }
-                        };
-
-                        match ns.data.borrow().value {
-                            NodeValue::Text(ref adj) => {
-                                *root += adj;
-                                ns.detach();
+                        let mut columns = TextColumns::new();
+                        columns.push(0, root.len(), ast.start_column, ast.end_column);
+
+                        while let Some(ns) = n.next_sibling() {
+                            let adj_ast = ns.data.borrow();
+                            match adj_ast.value {
+                                NodeValue::Text(ref adj) => {
+                                    columns.push(
+                                        root.len(),
+                                        adj.len(),
+                                        adj_ast.start_column,
+                                        adj_ast.end_column,
+                                    );
+                                    *root += adj;
+                                    ast.end_line = adj_ast.end_line;
+                                    ast.end_column = adj_ast.end_column;
+                                }
+                                _ => break,
                             }
-                            _ => break,
+                            ns.detach();
                         }
+
+                        last = self.postprocess_text_node(
+                            n,
+                            root,
+                            ast.start_line,
+                            &mut ast.start_column,
+                            &mut ast.end_column,
+                            &mut columns,
+                        );
                     }
                     NodeValue::Link(..) |
-                    NodeValue::Image(..) => {
-                        this_bracket = true;
-                        break;
-                    }
-                    _ => break,
+                    NodeValue::Image(..) => this_bracket = true,
+                    _ => (),
                 }
             }
 
@@ -1132,29 +1906,69 @@ impl<'a, 'o> Parser<'a, 'o> {
                 self.postprocess_text_nodes(n);
             }
 
-            nch = n.next_sibling();
+            nch = last.next_sibling();
+
+            // Autolinking at the very start of a text node leaves nothing of it behind.
+            let empty = match n.data.borrow().value {
+                NodeValue::Text(ref text) => text.is_empty(),
+                _ => false,
+            };
+            if empty {
+                n.detach();
+            }
         }
     }
 
-    fn postprocess_text_node(&mut self, node: &'a AstNode<'a>, text: &mut String) {
+    /// Processes task list markers and autolinks in a text node, returning the last node it was
+    /// split into.
+    fn postprocess_text_node(
+        &mut self,
+        node: &'a AstNode<'a>,
+        text: &mut String,
+        start_line: u32,
+        start_column: &mut usize,
+        end_column: &mut usize,
+        columns: &mut TextColumns,
+    ) -> &'a AstNode<'a> {
         if self.options.ext_tasklist {
-            self.process_tasklist(node, text);
+            self.process_tasklist(node, text, start_line, start_column, columns);
         }
 
         if self.options.ext_autolink {
-            autolink::process_autolinks(self.arena, node, text);
+            return autolink::process_autolinks(
+                self.arena,
+                node,
+                text,
+                start_line,
+                columns,
+                end_column,
+            );
         }
 
+        node
     }
 
-    fn process_tasklist(&mut self, node: &'a AstNode<'a>, text: &mut String) {
+    fn process_tasklist(
+        &mut self,
+        node: &'a AstNode<'a>,
+        text: &mut String,
+        start_line: u32,
+        start_column: &mut usize,
+        columns: &mut TextColumns,
+    ) {
         lazy_static! {
             static ref TASKLIST: Regex = Regex::new(r"\A(\s*\[([xX ])\])(?:\z|\s)").unwrap();
         }
 
-        let (active, end) = match TASKLIST.captures(text) {
+        let (active, end, text_start) = match TASKLIST.captures(text) {
             None => return,
-            Some(c) => (c.get(2).unwrap().as_str() != " ", c.get(1).unwrap().end()),
+            Some(c) => {
+                (
+                    c.get(2).unwrap().as_str() != " ",
+                    c.get(1).unwrap().end(),
+                    c.get(0).unwrap().end(),
+                )
+            }
         };
 
         let parent = node.parent().unwrap();
@@ -1172,17 +1986,19 @@ impl<'a, 'o> Parser<'a, 'o> {
             _ => return,
         }
 
-        *text = text[end..].to_string();
-        let checkbox = inlines::make_inline(
-            self.arena,
-            NodeValue::HtmlInline(
-                (if active {
-                     "<input type=\"checkbox\" disabled=\"\" checked=\"\" />"
-                 } else {
-                     "<input type=\"checkbox\" disabled=\"\" />"
-                 }).to_string(),
-            ),
-        );
+        // The space separating the marker from the item's text belongs to the marker, so that
+        // formatters needn't strip it from the text.
+        *text = text[text_start..].to_string();
+        let checkbox = inlines::make_inline(self.arena, NodeValue::TaskItem(active));
+        {
+            let mut checkbox_ast = checkbox.data.borrow_mut();
+            checkbox_ast.start_line = start_line;
+            checkbox_ast.start_column = *start_column;
+            checkbox_ast.end_line = start_line;
+            checkbox_ast.end_column = columns.end(end - 1);
+        }
+        *start_column = columns.start(text_start);
+        columns.skip(text_start);
         node.insert_before(checkbox);
     }
 
@@ -1192,7 +2008,10 @@ impl<'a, 'o> Parser<'a, 'o> {
             self.arena,
             self.options,
             content,
+            0,
+            &[],
             &mut self.refmap,
+            &self.footnote_defs,
             &delimiter_arena,
         );
 
@@ -1385,6 +2204,37 @@ fn unwrap_into_2<T, U>(tu: Option<(T, U)>, out_t: &mut T, out_u: &mut U) -> bool
     }
 }
 
+fn fix_zero_end_columns<'a>(node: &'a AstNode<'a>) {
+    for child in node.children() {
+        fix_zero_end_columns(child);
+    }
+
+    let mut ast = node.data.borrow_mut();
+    let ends_on_blank_line = ast.end_column == 0 &&
+        match ast.value {
+            NodeValue::List(..) |
+            NodeValue::Item(..) |
+            NodeValue::DescriptionList(..) |
+            NodeValue::DescriptionItem |
+            NodeValue::DescriptionDetails(..) => true,
+            _ => false,
+        };
+
+    if ends_on_blank_line {
+        if let Some(last_child) = node.last_child() {
+            let last_child = last_child.data.borrow();
+            ast.end_line = last_child.end_line;
+            ast.end_column = last_child.end_column;
+        }
+    }
+}
+
+fn ends_before_blank_line<'a>(node: &'a AstNode<'a>) -> bool {
+    node.next_sibling().map_or(false, |next| {
+        next.data.borrow().start_line > node.data.borrow().end_line + 1
+    })
+}
+
 fn lists_match(list_data: &NodeList, item_data: &NodeList) -> bool {
     list_data.list_type == item_data.list_type && list_data.delimiter == item_data.delimiter &&
         list_data.bullet_char == item_data.bullet_char
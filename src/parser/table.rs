@@ -43,6 +43,7 @@ pub fn try_opening_header<'a, 'o>(
 
     let mut alignments = vec![];
     for cell in marker_row {
+        let cell = cell.content;
         let left = !cell.is_empty() && cell.as_bytes()[0] == b':';
         let right = !cell.is_empty() && cell.as_bytes()[cell.len() - 1] == b':';
         alignments.push(if left && right {
@@ -56,13 +57,30 @@ pub fn try_opening_header<'a, 'o>(
         });
     }
 
-    let start_column = container.data.borrow().start_column;
+    let (start_line, start_column, end_column) = {
+        let ast = container.data.borrow();
+        (ast.start_line, ast.start_column, ast.start_column + ast.content.len() - 2)
+    };
     let table = parser.add_child(container, NodeValue::Table(alignments), start_column);
+    table.data.borrow_mut().start_line = start_line;
 
     let header = parser.add_child(table, NodeValue::TableRow(true), start_column);
-    for header_str in header_row {
-        let header_cell = parser.add_child(header, NodeValue::TableCell, start_column);
-        header_cell.data.borrow_mut().content = header_str;
+    set_line_position(header, start_line, start_column, end_column);
+    for cell in header_row {
+        let header_cell = parser.add_child(
+            header,
+            NodeValue::TableCell,
+            start_column + cell.start_offset,
+        );
+        set_line_position(
+            header_cell,
+            start_line,
+            start_column + cell.start_offset,
+            start_column + cell.end_offset,
+        );
+        let mut ast = header_cell.data.borrow_mut();
+        ast.content = cell.content;
+        ast.line_offsets.push(start_column + cell.content_offset - 1);
     }
 
     let offset = line.len() - 1 - parser.offset;
@@ -81,30 +99,34 @@ pub fn try_opening_row<'a, 'o>(
     if parser.blank {
         return None;
     }
-    let this_row = row(line).unwrap();
-    let new_row = parser.add_child(
-        container,
-        NodeValue::TableRow(false),
-        container.data.borrow().start_column,
-    );
+    let this_row = row(&line[parser.first_nonspace..]).unwrap();
+    let start_column = parser.first_nonspace + 1;
+    let line_number = parser.line_number;
+    let new_row = parser.add_child(container, NodeValue::TableRow(false), start_column);
+    set_line_position(new_row, line_number, start_column, line.len() - 1);
 
     let mut i = 0;
     while i < min(alignments.len(), this_row.len()) {
         let cell = parser.add_child(
             new_row,
             NodeValue::TableCell,
-            container.data.borrow().start_column,
+            start_column + this_row[i].start_offset,
         );
-        cell.data.borrow_mut().content = this_row[i].clone();
+        set_line_position(
+            cell,
+            line_number,
+            start_column + this_row[i].start_offset,
+            start_column + this_row[i].end_offset,
+        );
+        let mut ast = cell.data.borrow_mut();
+        ast.content = this_row[i].content.clone();
+        ast.line_offsets.push(start_column + this_row[i].content_offset - 1);
         i += 1;
     }
 
     while i < alignments.len() {
-        parser.add_child(
-            new_row,
-            NodeValue::TableCell,
-            container.data.borrow().start_column,
-        );
+        let cell = parser.add_child(new_row, NodeValue::TableCell, line.len() - 1);
+        set_line_position(cell, line_number, line.len() - 1, line.len() - 1);
         i += 1;
     }
 
@@ -114,7 +136,27 @@ pub fn try_opening_row<'a, 'o>(
     Some((new_row, false))
 }
 
-fn row(string: &str) -> Option<Vec<String>> {
+fn set_line_position<'a>(
+    node: &'a AstNode<'a>,
+    line: u32,
+    start_column: usize,
+    end_column: usize,
+) {
+    let mut ast = node.data.borrow_mut();
+    ast.start_line = line;
+    ast.start_column = start_column;
+    ast.end_line = line;
+    ast.end_column = end_column;
+}
+
+struct Cell {
+    start_offset: usize,
+    end_offset: usize,
+    content_offset: usize,
+    content: String,
+}
+
+fn row(string: &str) -> Option<Vec<Cell>> {
     let len = string.len();
     let mut v = vec![];
     let mut offset = 0;
@@ -131,7 +173,24 @@ fn row(string: &str) -> Option<Vec<String>> {
         if cell_matched > 0 || pipe_matched > 0 {
             let mut cell = unescape_pipes(&string[offset..offset + cell_matched]);
             trim(&mut cell);
-            v.push(cell);
+
+            let mut start_offset = offset;
+            while start_offset > 0 && string.as_bytes()[start_offset - 1] != b'|' {
+                start_offset -= 1;
+            }
+
+            let content_offset = offset +
+                string[offset..offset + cell_matched]
+                    .bytes()
+                    .take_while(|&c| c == b' ' || c == b'\t')
+                    .count();
+
+            v.push(Cell {
+                start_offset,
+                end_offset: offset + cell_matched - 1,
+                content_offset,
+                content: cell,
+            });
         }
 
         offset += cell_matched + pipe_matched;
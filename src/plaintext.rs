@@ -0,0 +1,289 @@
+use nodes;
+use nodes::{AstNode, ListDelimType, ListType, NodeValue};
+use parser::ComrakOptions;
+use std::io::{self, Write};
+use wrap::{LineWriter, Syntax};
+
+/// Formats an AST as plain text, modified by the given options.
+///
+/// Formatting is stripped, while paragraphs, headings and lists keep their structure.  Links are
+/// rendered as their text followed by the URL in parentheses.  Lines are wrapped to
+/// `options.width` if it is non-zero.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, format_plaintext, ComrakOptions};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "# Hello\n\nSee *the* [docs](/docs).\n\n1. One\n2. Two\n",
+///                           &options);
+///
+/// assert_eq!(format_plaintext(root, &options),
+///            "Hello\n\nSee the docs (/docs).\n\n1. One\n2. Two\n");
+/// # }
+/// ```
+pub fn format_document<'a>(root: &'a AstNode<'a>, options: &ComrakOptions) -> String {
+    let mut output = vec![];
+    format_document_to(root, options, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+/// Formats an AST as plain text, modified by the given options, writing the result to `output`.
+pub fn format_document_to<'a>(
+    root: &'a AstNode<'a>,
+    options: &ComrakOptions,
+    output: &mut Write,
+) -> io::Result<()> {
+    let mut f = PlaintextFormatter::new(options);
+    if f.format_node(root, true) {
+        for n in root.children() {
+            f.format(n);
+            f.w.flush_lines(output)?;
+        }
+        f.format_node(root, false);
+    }
+    f.w.finish(output)
+}
+
+struct PlaintextFormatter<'o> {
+    options: &'o ComrakOptions,
+    w: LineWriter,
+}
+
+impl<'o> Write for PlaintextFormatter<'o> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output(buf, false);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'o> PlaintextFormatter<'o> {
+    fn new(options: &'o ComrakOptions) -> Self {
+        PlaintextFormatter {
+            options: options,
+            w: LineWriter::new(options.width, Syntax::Text),
+        }
+    }
+
+    fn output(&mut self, buf: &[u8], wrap: bool) {
+        self.w.output(buf, wrap, |w, rest| {
+            w.push(rest[0]);
+            1
+        });
+    }
+
+    fn cr(&mut self) {
+        self.w.cr();
+    }
+
+    fn blankline(&mut self) {
+        self.w.blankline();
+    }
+
+    fn format<'a>(&mut self, node: &'a AstNode<'a>) {
+        if self.format_node(node, true) {
+            for n in node.children() {
+                self.format(n);
+            }
+            self.format_node(node, false);
+        }
+    }
+
+    fn get_in_tight_list_item<'a>(&self, node: &'a AstNode<'a>) -> bool {
+        let block = match nodes::containing_block(node) {
+            Some(block) => block,
+            None => return false,
+        };
+
+        for item in block.ancestors().take(2) {
+            match item.data.borrow().value {
+                NodeValue::Item(..) => {
+                    if let NodeValue::List(ref nl) = item.parent().unwrap().data.borrow().value {
+                        return nl.tight;
+                    }
+                }
+                NodeValue::DescriptionDetails(..) => return nodes::in_tight_description_list(item),
+                _ => (),
+            }
+        }
+
+        false
+    }
+
+    fn format_node<'a>(&mut self, node: &'a AstNode<'a>, entering: bool) -> bool {
+        let allow_wrap = self.options.width > 0 && !self.options.hardbreaks;
+
+        if !(match node.data.borrow().value {
+                 NodeValue::Item(..) => true,
+                 _ => false,
+             } && node.previous_sibling().is_none() && entering)
+        {
+            self.w.in_tight_list_item = self.get_in_tight_list_item(node);
+        }
+
+        match node.data.borrow().value {
+            NodeValue::Document |
+            NodeValue::FrontMatter(..) |
+            NodeValue::Emph |
+            NodeValue::Strong |
+            NodeValue::Strikethrough |
+            NodeValue::Superscript |
+            NodeValue::Subscript |
+            NodeValue::Image(..) => (),
+            NodeValue::BlockQuote |
+            NodeValue::List(..) |
+            NodeValue::Heading(..) |
+            NodeValue::Paragraph |
+            NodeValue::Table(..) => {
+                if !entering {
+                    self.blankline();
+                }
+            }
+            NodeValue::Item(..) => {
+                let parent = match node.parent().unwrap().data.borrow().value {
+                    NodeValue::List(ref nl) => *nl,
+                    _ => unreachable!(),
+                };
+
+                let marker = if parent.list_type == ListType::Bullet {
+                    "- ".to_string()
+                } else {
+                    let list_number = parent.start + node.preceding_siblings().count() - 1;
+                    let list_delim = if parent.delimiter == ListDelimType::Paren {
+                        ")"
+                    } else {
+                        "."
+                    };
+                    format!("{}{} ", list_number, list_delim)
+                };
+
+                if entering {
+                    self.write_all(marker.as_bytes()).unwrap();
+                    self.w.prefix.extend(marker.bytes().map(|_| b' '));
+                } else {
+                    let new_len = self.w.prefix.len() - marker.len();
+                    self.w.prefix.truncate(new_len);
+                    self.cr();
+                }
+            }
+            NodeValue::CodeBlock(ref ncb) |
+            NodeValue::MathBlock(ref ncb) => {
+                if entering {
+                    self.write_all(ncb.literal.as_bytes()).unwrap();
+                    self.blankline();
+                }
+            }
+            NodeValue::HtmlBlock(..) => (),
+            NodeValue::ThematicBreak => {
+                if entering {
+                    self.blankline();
+                }
+            }
+            NodeValue::Text(ref literal) => {
+                if entering {
+                    self.output(literal.as_bytes(), allow_wrap);
+                }
+            }
+            NodeValue::LineBreak => {
+                if entering {
+                    self.cr();
+                }
+            }
+            NodeValue::SoftBreak => {
+                if entering {
+                    if self.options.hardbreaks || self.options.width == 0 {
+                        self.cr();
+                    } else {
+                        self.output(b" ", allow_wrap);
+                    }
+                }
+            }
+            NodeValue::Code(ref literal) => {
+                if entering {
+                    self.output(literal.as_bytes(), allow_wrap);
+                }
+            }
+            NodeValue::Math(ref nm) => {
+                if entering {
+                    self.output(nm.literal.as_bytes(), false);
+                }
+            }
+            NodeValue::HtmlInline(..) => (),
+            NodeValue::TaskItem(checked) => {
+                if entering {
+                    self.output(if checked { b"[x] " } else { b"[ ] " }, false);
+                }
+            }
+            NodeValue::Link(ref nl) => {
+                if !entering && !is_autolink(node, &nl.url) {
+                    self.output(b" (", allow_wrap);
+                    self.output(nl.url.as_bytes(), false);
+                    self.output(b")", false);
+                }
+            }
+            NodeValue::FootnoteDefinition(ref name) => {
+                if entering {
+                    write!(self, "[{}] ", name).unwrap();
+                    self.w.prefix.extend(b"    ");
+                } else {
+                    let new_len = self.w.prefix.len() - 4;
+                    self.w.prefix.truncate(new_len);
+                    self.blankline();
+                }
+            }
+            NodeValue::DescriptionList(..) |
+            NodeValue::DescriptionItem => {
+                if !entering {
+                    self.blankline();
+                }
+            }
+            NodeValue::DescriptionTerm => (),
+            NodeValue::DescriptionDetails(..) => {
+                if entering {
+                    self.w.prefix.extend(b"    ");
+                } else {
+                    let new_len = self.w.prefix.len() - 4;
+                    self.w.prefix.truncate(new_len);
+                    self.cr();
+                }
+            }
+            NodeValue::FootnoteReference(ref name) => {
+                if entering {
+                    write!(self, "[{}]", name).unwrap();
+                }
+            }
+            NodeValue::TableRow(..) => {
+                if entering {
+                    self.cr();
+                }
+            }
+            NodeValue::TableCell => {
+                if !entering && node.next_sibling().is_some() {
+                    self.output(b" | ", false);
+                }
+            }
+        };
+        true
+    }
+}
+
+/// Returns whether the link's text is just its URL, in which case the URL needn't be repeated.
+fn is_autolink<'a>(node: &'a AstNode<'a>, url: &str) -> bool {
+    let child = match node.first_child() {
+        Some(child) if child.next_sibling().is_none() => child,
+        _ => return false,
+    };
+
+    match child.data.borrow().value {
+        NodeValue::Text(ref text) => {
+            url == text || (url.starts_with("mailto:") && &url[7..] == text)
+        }
+        _ => false,
+    }
+}
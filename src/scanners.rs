@@ -270,3 +270,22 @@ pub fn table_row_end(line: &str) -> Option<usize> {
 
     search(&RE, line)
 }
+
+pub fn footnote_definition(line: &str) -> Option<usize> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\A(?:\[\^[^\]\r\n\x00\t ]+\]:[ \t]*)").unwrap();
+    }
+
+    search(&RE, line)
+}
+
+pub fn dangerous_url(line: &str) -> bool {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r"\A(?i:javascript:|vbscript:|file:|data:)").unwrap();
+        static ref SAFE_DATA_RE: Regex = Regex::new(
+            r"\A(?i:data:image/(?:png|gif|jpeg|webp))").unwrap();
+    }
+
+    is_match(&RE, line) && !is_match(&SAFE_DATA_RE, line)
+}
@@ -219,3 +219,72 @@ pub fn normalize_reference_label(i: &str) -> String {
     }
     v
 }
+
+/// The offset by which the closing front matter delimiter line must have started; past it, the
+/// block is treated as ordinary Markdown.
+pub const MAX_FRONT_MATTER_LENGTH: usize = 65536;
+
+/// Returns the length of the front matter block at the start of `s`, if any: an opening
+/// `delimiter` line, a closing `delimiter` line, and any blank lines following it.
+pub fn front_matter_len(s: &str, delimiter: &str) -> Option<usize> {
+    let mut lines = LinesWithEndings { s: s };
+    let mut len = match lines.next() {
+        Some(first) if first.len() > delimiter.len() && trim_line_end(first, false) == delimiter => {
+            first.len()
+        }
+        _ => return None,
+    };
+
+    let mut closed = false;
+    for line in lines {
+        if !closed {
+            if len >= MAX_FRONT_MATTER_LENGTH {
+                return None;
+            }
+            closed = trim_line_end(line, true) == delimiter;
+        } else if !is_blank(line) {
+            break;
+        }
+        len += line.len();
+    }
+
+    if closed { Some(len) } else { None }
+}
+
+fn trim_line_end(line: &str, spaces: bool) -> &str {
+    let bytes = line.as_bytes();
+    let mut len = line.len();
+    while len > 0 &&
+        (is_line_end_char(bytes[len - 1]) || spaces && is_space_or_tab(bytes[len - 1]))
+    {
+        len -= 1;
+    }
+    &line[..len]
+}
+
+struct LinesWithEndings<'a> {
+    s: &'a str,
+}
+
+impl<'a> Iterator for LinesWithEndings<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.s.is_empty() {
+            return None;
+        }
+
+        let bytes = self.s.as_bytes();
+        let mut end = bytes
+            .iter()
+            .position(|&c| is_line_end_char(c))
+            .map_or(bytes.len(), |i| i + 1);
+        if end < bytes.len() && bytes[end - 1] == b'\r' && bytes[end] == b'\n' {
+            end += 1;
+        }
+
+        let line = &self.s[..end];
+        self.s = &self.s[end..];
+        Some(line)
+    }
+}
@@ -0,0 +1,69 @@
+//! A syntax highlighter for fenced code blocks built on
+//! [syntect](https://github.com/trishume/syntect).  Requires the `syntect` feature.
+
+use html::SyntaxHighlighter;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::highlighted_html_for_string;
+use syntect::parsing::SyntaxSet;
+
+/// Highlights fenced code blocks using syntect's bundled syntax definitions and themes.
+///
+/// The language is taken from the first word of the info string; code blocks in unknown
+/// languages are rendered as normal.  The output is a `<pre>` element with inline styles.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, ComrakOptions, HtmlFormatter};
+/// # use comrak::syntect_highlighter::SyntectHighlighter;
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "```rust\nfn main() {}\n```\n", &options);
+///
+/// let highlighter = SyntectHighlighter::new("InspiredGitHub").unwrap();
+/// let mut html = vec![];
+/// HtmlFormatter::new(&options, &mut html)
+///     .with_highlighter(&highlighter)
+///     .format_document(root)
+///     .unwrap();
+/// assert!(String::from_utf8(html).unwrap().starts_with("<pre style=\"background-color:#ffffff;\">\n<span"));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SyntectHighlighter {
+    syntax_set: SyntaxSet,
+    theme: Theme,
+}
+
+impl SyntectHighlighter {
+    /// Creates a highlighter using the named bundled theme, such as `"InspiredGitHub"` or
+    /// `"base16-ocean.dark"`.  Returns `None` if there is no such theme.
+    pub fn new(theme: &str) -> Option<Self> {
+        ThemeSet::load_defaults()
+            .themes
+            .remove(theme)
+            .map(|theme| {
+                SyntectHighlighter {
+                    syntax_set: SyntaxSet::load_defaults_newlines(),
+                    theme: theme,
+                }
+            })
+    }
+}
+
+impl SyntaxHighlighter for SyntectHighlighter {
+    fn highlight(&self, info: &str, code: &str) -> Option<String> {
+        let lang = match info.split_whitespace().next() {
+            Some(lang) => lang,
+            None => return None,
+        };
+
+        let syntax = match self.syntax_set.find_syntax_by_token(lang) {
+            Some(syntax) => syntax,
+            None => return None,
+        };
+
+        highlighted_html_for_string(code, &self.syntax_set, syntax, &self.theme).ok()
+    }
+}
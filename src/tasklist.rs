@@ -0,0 +1,71 @@
+use nodes::NodeValue;
+use parser::{parse_document, ComrakOptions};
+use typed_arena::Arena;
+
+/// Toggles the checkbox of the `index`th task list item (counting from zero, in document order)
+/// in the Markdown text `md`, returning the modified text.
+///
+/// Only the item's `[ ]` or `[x]` marker is changed; the rest of the text is returned exactly as
+/// given.  Items are numbered the same way as the `data-task-index` attributes written when
+/// `tasklist_interactive` is set, so `options` should be those the document is rendered with.
+/// Returns `None` if there is no such item.
+///
+/// ```
+/// # use comrak::{toggle_task_item, ComrakOptions};
+/// let mut options = ComrakOptions::default();
+/// options.ext_tasklist = true;
+/// let md = "* [x] Done\n* [ ] *Not* done\n";
+///
+/// assert_eq!(toggle_task_item(md, 1, &options).unwrap(),
+///            "* [x] Done\n* [x] *Not* done\n");
+/// assert_eq!(toggle_task_item(md, 0, &options).unwrap(),
+///            "* [ ] Done\n* [ ] *Not* done\n");
+/// assert_eq!(toggle_task_item(md, 2, &options), None);
+/// ```
+pub fn toggle_task_item(md: &str, index: usize, options: &ComrakOptions) -> Option<String> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, md, options);
+
+    let (line, column) = {
+        let task = root.descendants()
+            .filter(|n| match n.data.borrow().value {
+                NodeValue::TaskItem(..) => true,
+                _ => false,
+            })
+            .nth(index)?;
+        let ast = task.data.borrow();
+        (ast.end_line, ast.end_column)
+    };
+
+    // The marker's end column is that of its closing bracket; the check is just before it.
+    let offset = line_start(md, line)? + column.checked_sub(2)?;
+    let check = match md.as_bytes().get(offset) {
+        Some(&b' ') => "x",
+        Some(&b'x') | Some(&b'X') => " ",
+        _ => return None,
+    };
+
+    let mut result = String::with_capacity(md.len());
+    result.push_str(&md[..offset]);
+    result.push_str(check);
+    result.push_str(&md[offset + 1..]);
+    Some(result)
+}
+
+/// Finds the byte offset at which the given (1-based) line of `md` starts, treating `\n`, `\r\n`
+/// and `\r` as line endings as the parser does.
+fn line_start(md: &str, line: u32) -> Option<usize> {
+    let bytes = md.as_bytes();
+    let mut current = 1;
+    let mut i = 0;
+    while current < line {
+        match bytes.get(i) {
+            None => return None,
+            Some(&b'\n') => current += 1,
+            Some(&b'\r') if bytes.get(i + 1) != Some(&b'\n') => current += 1,
+            _ => (),
+        }
+        i += 1;
+    }
+    Some(i)
+}
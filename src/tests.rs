@@ -1,6 +1,19 @@
-use {Arena, parse_document, ComrakOptions};
+use {Arena, parse_document, markdown_to_html, toggle_task_item, ComrakOptions, Error,
+     StreamingParser};
 use cm;
 use html;
+use latex;
+#[cfg(feature = "serde")]
+use json;
+use html::{HtmlFormatter, HtmlRenderer, SyntaxHighlighter};
+use nodes::{AstNode, ListType, NodeHeading, NodeLink, NodeList, NodeValue};
+use toc;
+use visitor::{walk, Visit, Visitor};
+use man;
+use plaintext;
+use xml;
+use std::io;
+use std::io::Write;
 
 fn compare_strs(output: &str, expected: &str, kind: &str) {
     if output != expected {
@@ -474,9 +487,1573 @@ fn tasklist() {
     );
 }
 
+#[test]
+fn tasklist_nodes() {
+    let options = ComrakOptions {
+        ext_tasklist: true,
+        safe: true,
+        ..ComrakOptions::default()
+    };
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, "- [ ] Red\n- [X] *Green*\n- [y] Blue\n", &options);
+
+    let checked: Vec<_> = root.descendants()
+        .filter_map(|n| match n.data.borrow().value {
+            NodeValue::TaskItem(checked) => Some(checked),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(checked, vec![false, true]);
+
+    compare_strs(
+        &cm::format_document(root, &options),
+        "  - [ ] Red\n  - [x] *Green*\n  - \\[y\\] Blue\n",
+        "tasklist commonmark",
+    );
+    compare_strs(
+        &html::format_document(root, &options),
+        concat!(
+            "<ul>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" /> Red</li>\n",
+            "<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> <em>Green</em></li>\n",
+            "<li>[y] Blue</li>\n",
+            "</ul>\n"
+        ),
+        "tasklist safe html",
+    );
+}
+
+#[test]
+fn tasklist_interactive() {
+    let options = ComrakOptions {
+        ext_tasklist: true,
+        ext_footnotes: true,
+        tasklist_interactive: true,
+        ..ComrakOptions::default()
+    };
+
+    // The footnote definition is moved to the end of the document, so its task comes last.
+    let input = "See[^n].\r\n\r\n[^n]:\r\n    - [X] Noted\r\n\r\n> 1.  [ ]\tQuoted\r\n>     - [x] Nested\r\n";
+
+    compare_strs(
+        &markdown_to_html(input, &options),
+        concat!(
+            "<p>See<sup class=\"footnote-ref\"><a href=\"#fn1\" id=\"fnref1\">1</a></sup>.</p>\n",
+            "<blockquote>\n",
+            "<ol>\n",
+            "<li><input type=\"checkbox\" data-task-index=\"0\" data-task-line=\"6\" /> Quoted\n",
+            "<ul>\n",
+            "<li><input type=\"checkbox\" data-task-index=\"1\" data-task-line=\"7\" checked=\"\" /> ",
+            "Nested</li>\n",
+            "</ul>\n",
+            "</li>\n",
+            "</ol>\n",
+            "</blockquote>\n",
+            "<section class=\"footnotes\">\n",
+            "<ol>\n",
+            "<li id=\"fn1\">\n",
+            "<ul>\n",
+            "<li><input type=\"checkbox\" data-task-index=\"2\" data-task-line=\"4\" checked=\"\" /> ",
+            "Noted</li>\n",
+            "</ul>\n",
+            " <a href=\"#fnref1\" class=\"footnote-backref\">↩</a>\n",
+            "</li>\n",
+            "</ol>\n",
+            "</section>\n"
+        ),
+        "interactive tasklist html",
+    );
+
+    compare_strs(
+        &toggle_task_item(input, 0, &options).unwrap(),
+        &input.replace("[ ]", "[x]"),
+        "toggled quoted task",
+    );
+    compare_strs(
+        &toggle_task_item(input, 1, &options).unwrap(),
+        &input.replace("[x]", "[ ]"),
+        "toggled nested task",
+    );
+    compare_strs(
+        &toggle_task_item(input, 2, &options).unwrap(),
+        &input.replace("[X]", "[ ]"),
+        "toggled footnote task",
+    );
+    assert_eq!(toggle_task_item(input, 3, &options), None);
+}
+
+#[test]
+fn description_lists() {
+    html_opts(
+        concat!(
+            "Term\n",
+            ": Details.\n",
+            ": More details\n",
+            "  continued.\n",
+            "  - nested\n",
+            "\n",
+            "Another *term*\n",
+            ": Its details.\n",
+            "\n",
+            "> Quoted\n",
+            "> : Details.\n",
+            "\n",
+            "Not a term.\n",
+            "\n",
+            "\\: Nor details.\n"
+        ),
+        concat!(
+            "<dl>\n",
+            "<dt>Term</dt>\n",
+            "<dd>Details.</dd>\n",
+            "<dd>More details\n",
+            "continued.\n",
+            "<ul>\n",
+            "<li>nested</li>\n",
+            "</ul>\n",
+            "</dd>\n",
+            "<dt>Another <em>term</em></dt>\n",
+            "<dd>Its details.</dd>\n",
+            "</dl>\n",
+            "<blockquote>\n",
+            "<dl>\n",
+            "<dt>Quoted</dt>\n",
+            "<dd>Details.</dd>\n",
+            "</dl>\n",
+            "</blockquote>\n",
+            "<p>Not a term.</p>\n",
+            "<p>: Nor details.</p>\n"
+        ),
+        |opts| opts.ext_description_lists = true,
+    );
+
+    html_opts(
+        concat!(
+            "Term\n",
+            "\n",
+            ": Details.\n",
+            "\n",
+            "Another term\n",
+            ": Its details.\n",
+            "\n",
+            "  More.\n"
+        ),
+        concat!(
+            "<dl>\n",
+            "<dt>Term</dt>\n",
+            "<dd>\n",
+            "<p>Details.</p>\n",
+            "</dd>\n",
+            "<dt>Another term</dt>\n",
+            "<dd>\n",
+            "<p>Its details.</p>\n",
+            "<p>More.</p>\n",
+            "</dd>\n",
+            "</dl>\n"
+        ),
+        |opts| opts.ext_description_lists = true,
+    );
+
+    html("Term\n: Details.\n", "<p>Term\n: Details.</p>\n");
+}
+
+#[test]
+fn math() {
+    html_opts(
+        concat!(
+            "Let $x_1 * x_2$ be $$\\frac{a}{b}$$, *not $a* b$*.\n",
+            "\n",
+            "It costs $5 and $10.\n",
+            "\n",
+            "Neither $ 3 $ nor \\$x$ is math, but $a\\$b$ is.\n",
+            "\n",
+            "``` math\n",
+            "a_1 < b_1\n",
+            "```\n",
+            "\n",
+            "- $a\n",
+            "  b$\n"
+        ),
+        concat!(
+            "<p>Let <span class=\"math math-inline\">x_1 * x_2</span> be ",
+            "<span class=\"math math-display\">\\frac{a}{b}</span>, ",
+            "<em>not <span class=\"math math-inline\">a* b</span></em>.</p>\n",
+            "<p>It costs $5 and $10.</p>\n",
+            "<p>Neither $ 3 $ nor $x$ is math, but ",
+            "<span class=\"math math-inline\">a\\$b</span> is.</p>\n",
+            "<div class=\"math math-display\">a_1 &lt; b_1\n</div>\n",
+            "<ul>\n",
+            "<li><span class=\"math math-inline\">a\nb</span></li>\n",
+            "</ul>\n"
+        ),
+        |opts| opts.ext_math = true,
+    );
+
+    html(
+        "$a*b$ and $c*d$\n",
+        "<p>$a<em>b$ and $c</em>d$</p>\n",
+    );
+}
+
 #[test]
 fn superscript() {
     html_opts(concat!("e = mc^2^.\n"),
               concat!("<p>e = mc<sup>2</sup>.</p>\n"),
               |opts| opts.ext_superscript = true);
 }
+
+#[test]
+fn subscript() {
+    html_opts(
+        concat!(
+            "H~2~O is ~~not~~ wet, nor ~~a ~b~ c~~ nor ~a ~~b~~ c~.\n",
+            "\n",
+            "Both ~~~x~~~ and \\~1~ aren't.\n"
+        ),
+        concat!(
+            "<p>H<sub>2</sub>O is <del>not</del> wet, nor <del>a <sub>b</sub> c</del> ",
+            "nor <sub>a <del>b</del> c</sub>.</p>\n",
+            "<p>Both ~~~x~~~ and ~1~ aren't.</p>\n"
+        ),
+        |opts| {
+            opts.ext_subscript = true;
+            opts.ext_strikethrough = true;
+        },
+    );
+
+    html_opts(
+        "H~2~O is ~~not~~ wet.\n",
+        "<p>H<sub>2</sub>O is ~~not~~ wet.</p>\n",
+        |opts| opts.ext_subscript = true,
+    );
+}
+
+#[test]
+fn smart_punctuation() {
+    html_opts(
+        concat!(
+            "\"Hello,\" said the spider. \"'Shelob' is my name.\"\n",
+            "\n",
+            "'A', 'B', and 'C' are letters.\n",
+            "\n",
+            "It's a \"[link](/u)\".\n",
+            "\n",
+            "em---em en--en - -- --- ----- ...\n"
+        ),
+        concat!(
+            "<p>“Hello,” said the spider. “‘Shelob’ is my name.”</p>\n",
+            "<p>‘A’, ‘B’, and ‘C’ are letters.</p>\n",
+            "<p>It’s a “<a href=\"/u\">link</a>”.</p>\n",
+            "<p>em—em en–en - – — —– …</p>\n"
+        ),
+        |opts| opts.smart = true,
+    );
+}
+
+#[test]
+fn header_ids() {
+    html_opts(
+        concat!(
+            "# Hi.\n",
+            "## Hi 1.\n",
+            "### Hi.\n",
+            "#### Hello.\n",
+            "##### Hi.\n",
+            "###### Hello *world* `code`!\n",
+            "Überschrift\n",
+            "===========\n"
+        ),
+        concat!(
+            "<h1><a href=\"#hi\" aria-hidden=\"true\" class=\"anchor\" id=\"user-content-hi\"></a>",
+            "Hi.</h1>\n",
+            "<h2><a href=\"#hi-1\" aria-hidden=\"true\" class=\"anchor\" id=\"user-content-hi-1\"></a>",
+            "Hi 1.</h2>\n",
+            "<h3><a href=\"#hi-2\" aria-hidden=\"true\" class=\"anchor\" id=\"user-content-hi-2\"></a>",
+            "Hi.</h3>\n",
+            "<h4><a href=\"#hello\" aria-hidden=\"true\" class=\"anchor\" id=\"user-content-hello\"></a>",
+            "Hello.</h4>\n",
+            "<h5><a href=\"#hi-3\" aria-hidden=\"true\" class=\"anchor\" id=\"user-content-hi-3\"></a>",
+            "Hi.</h5>\n",
+            "<h6><a href=\"#hello-world-code\" aria-hidden=\"true\" class=\"anchor\" ",
+            "id=\"user-content-hello-world-code\"></a>Hello <em>world</em> <code>code</code>!</h6>\n",
+            "<h1><a href=\"#überschrift\" aria-hidden=\"true\" class=\"anchor\" ",
+            "id=\"user-content-überschrift\"></a>Überschrift</h1>\n"
+        ),
+        |opts| opts.ext_header_ids = Some("user-content-".to_string()),
+    );
+}
+
+#[test]
+fn table_of_contents() {
+    let arena = Arena::new();
+    let options = ComrakOptions {
+        ext_header_ids: Some("user-content-".to_string()),
+        ..ComrakOptions::default()
+    };
+
+    let root = parse_document(
+        &arena,
+        concat!(
+            "# Title\n",
+            "\n",
+            "[[_TOC_]]\n",
+            "\n",
+            "### Deep *dive*\n",
+            "\n",
+            "## Usage\n",
+            "\n",
+            "# Title\n"
+        ),
+        &options,
+    );
+
+    let entries = toc::table_of_contents(root);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].children.len(), 2);
+    assert_eq!(entries[0].children[0].level, 3);
+    assert_eq!(entries[0].children[0].text, "Deep dive");
+    assert_eq!(entries[0].children[0].slug, "deep-dive");
+    assert_eq!(entries[0].children[0].line, 5);
+    assert_eq!(entries[1].slug, "title-1");
+
+    assert_eq!(toc::replace_toc_markers(&arena, root), 1);
+    compare_strs(
+        &html::format_document(root, &options),
+        concat!(
+            "<h1><a href=\"#title\" aria-hidden=\"true\" class=\"anchor\" ",
+            "id=\"user-content-title\"></a>Title</h1>\n",
+            "<ul>\n",
+            "<li><a href=\"#title\">Title</a>\n",
+            "<ul>\n",
+            "<li><a href=\"#deep-dive\">Deep dive</a></li>\n",
+            "<li><a href=\"#usage\">Usage</a></li>\n",
+            "</ul>\n",
+            "</li>\n",
+            "<li><a href=\"#title-1\">Title</a></li>\n",
+            "</ul>\n",
+            "<h3><a href=\"#deep-dive\" aria-hidden=\"true\" class=\"anchor\" ",
+            "id=\"user-content-deep-dive\"></a>Deep <em>dive</em></h3>\n",
+            "<h2><a href=\"#usage\" aria-hidden=\"true\" class=\"anchor\" ",
+            "id=\"user-content-usage\"></a>Usage</h2>\n",
+            "<h1><a href=\"#title-1\" aria-hidden=\"true\" class=\"anchor\" ",
+            "id=\"user-content-title-1\"></a>Title</h1>\n"
+        ),
+        "toc",
+    );
+}
+
+#[test]
+fn front_matter() {
+    let input = concat!(
+        "---\n",
+        "title: Front matter\n",
+        "---\n",
+        "\n",
+        "# Heading\n",
+        "\n",
+        "---\n",
+        "\n",
+        "Text.\n"
+    );
+
+    let arena = Arena::new();
+    let options = ComrakOptions {
+        front_matter_delimiter: Some("---".to_string()),
+        sourcepos: true,
+        ..ComrakOptions::default()
+    };
+    let root = parse_document(&arena, input, &options);
+
+    match root.first_child().unwrap().data.borrow().value {
+        ::nodes::NodeValue::FrontMatter(ref literal) => {
+            compare_strs(literal, "---\ntitle: Front matter\n---\n\n", "front matter")
+        }
+        ref value => panic!("expected front matter, got {:?}", value),
+    }
+
+    compare_strs(
+        &html::format_document(root, &options),
+        concat!(
+            "<h1 data-sourcepos=\"5:1-5:9\">Heading</h1>\n",
+            "<hr data-sourcepos=\"7:1-7:3\" />\n",
+            "<p data-sourcepos=\"9:1-9:5\">Text.</p>\n"
+        ),
+        "html",
+    );
+    compare_strs(
+        &cm::format_document(root, &options),
+        concat!(
+            "---\n",
+            "title: Front matter\n",
+            "---\n",
+            "\n",
+            "# Heading\n",
+            "\n",
+            "-----\n",
+            "\n",
+            "Text.\n"
+        ),
+        "commonmark",
+    );
+
+    html_opts(
+        "---\nNot front matter.\n",
+        "<hr />\n<p>Not front matter.</p>\n",
+        |opts| opts.front_matter_delimiter = Some("---".to_string()),
+    );
+}
+
+#[test]
+fn footnotes() {
+    html_opts(
+        concat!(
+            "Here is a[^nowhere] footnote reference,[^1] and another.[^longnote]\n",
+            "\n",
+            "This is another note.[^note]\n",
+            "\n",
+            "[^1]: Here is the footnote.\n",
+            "\n",
+            "[^longnote]: Here's one with multiple blocks.\n",
+            "\n",
+            "    Subsequent paragraphs are indented.\n",
+            "\n",
+            "        code\n",
+            "\n",
+            "This is regular content.\n",
+            "\n",
+            "[^note]: This is the last footnote.\n"
+        ),
+        concat!(
+            "<p>Here is a[^nowhere] footnote reference,<sup class=\"footnote-ref\">",
+            "<a href=\"#fn1\" id=\"fnref1\">1</a></sup> and another.<sup class=\"footnote-ref\">",
+            "<a href=\"#fn2\" id=\"fnref2\">2</a></sup></p>\n",
+            "<p>This is another note.<sup class=\"footnote-ref\"><a href=\"#fn3\" id=\"fnref3\">",
+            "3</a></sup></p>\n",
+            "<p>This is regular content.</p>\n",
+            "<section class=\"footnotes\">\n",
+            "<ol>\n",
+            "<li id=\"fn1\">\n",
+            "<p>Here is the footnote. <a href=\"#fnref1\" class=\"footnote-backref\">↩</a></p>\n",
+            "</li>\n",
+            "<li id=\"fn2\">\n",
+            "<p>Here's one with multiple blocks.</p>\n",
+            "<p>Subsequent paragraphs are indented.</p>\n",
+            "<pre><code>code\n",
+            "</code></pre>\n",
+            " <a href=\"#fnref2\" class=\"footnote-backref\">↩</a>\n",
+            "</li>\n",
+            "<li id=\"fn3\">\n",
+            "<p>This is the last footnote. <a href=\"#fnref3\" class=\"footnote-backref\">↩</a></p>\n",
+            "</li>\n",
+            "</ol>\n",
+            "</section>\n"
+        ),
+        |opts| opts.ext_footnotes = true,
+    );
+
+    html_opts(
+        "a[^1] b[^1]\n\n[^1]: Twice.\n",
+        concat!(
+            "<p>a<sup class=\"footnote-ref\"><a href=\"#fn1\" id=\"fnref1\">1</a></sup> ",
+            "b<sup class=\"footnote-ref\"><a href=\"#fn1\" id=\"fnref1-2\">1</a></sup></p>\n",
+            "<section class=\"footnotes\">\n",
+            "<ol>\n",
+            "<li id=\"fn1\">\n",
+            "<p>Twice. <a href=\"#fnref1\" class=\"footnote-backref\">↩</a> ",
+            "<a href=\"#fnref1-2\" class=\"footnote-backref\">↩<sup>2</sup></a></p>\n",
+            "</li>\n",
+            "</ol>\n",
+            "</section>\n"
+        ),
+        |opts| opts.ext_footnotes = true,
+    );
+}
+
+#[test]
+fn footnotes_commonmark() {
+    let options = ComrakOptions {
+        ext_footnotes: true,
+        ..ComrakOptions::default()
+    };
+
+    let arena = Arena::new();
+    let root = parse_document(
+        &arena,
+        concat!(
+            "Hi[^a] and[^b].\n",
+            "\n",
+            "[^a]: Para\n",
+            "\n",
+            "    > quoted\n",
+            "\n",
+            "[^b]: - x\n",
+            "    - y\n"
+        ),
+        &options,
+    );
+    let md = cm::format_document(root, &options);
+    compare_strs(
+        &md,
+        concat!(
+            "Hi[^a] and[^b].\n",
+            "\n",
+            "[^a]: Para\n",
+            "    \n",
+            "    > quoted\n",
+            "\n",
+            "[^b]:\n",
+            "      - x\n",
+            "      - y\n"
+        ),
+        "footnotes commonmark",
+    );
+
+    let round_tripped = parse_document(&arena, &md, &options);
+    compare_strs(
+        &html::format_document(round_tripped, &options),
+        &html::format_document(root, &options),
+        "footnotes commonmark roundtrip",
+    );
+}
+
+#[test]
+fn safety() {
+    html_opts(
+        concat!(
+            "[data:png](data:image/png/x)\n\n",
+            "[data:gif](data:image/gif/x)\n\n",
+            "[data:jpeg](data:image/jpeg/x)\n\n",
+            "[data:webp](data:image/webp/x)\n\n",
+            "[data:malicious](data:malicious/x)\n\n",
+            "[javascript:malicious](javascript:malicious)\n\n",
+            "[vbscript:malicious](vbscript:malicious)\n\n",
+            "[file:malicious](file:malicious)\n\n",
+            "![JavaScript:malicious](JavaScript:malicious)\n\n",
+            "<script>\n",
+            "alert('xyz');\n",
+            "</script>\n\n",
+            "Possibly <marquee>annoying</marquee>.\n"
+        ),
+        concat!(
+            "<p><a href=\"data:image/png/x\">data:png</a></p>\n",
+            "<p><a href=\"data:image/gif/x\">data:gif</a></p>\n",
+            "<p><a href=\"data:image/jpeg/x\">data:jpeg</a></p>\n",
+            "<p><a href=\"data:image/webp/x\">data:webp</a></p>\n",
+            "<p><a href=\"\">data:malicious</a></p>\n",
+            "<p><a href=\"\">javascript:malicious</a></p>\n",
+            "<p><a href=\"\">vbscript:malicious</a></p>\n",
+            "<p><a href=\"\">file:malicious</a></p>\n",
+            "<p><img src=\"\" alt=\"JavaScript:malicious\" /></p>\n",
+            "<!-- raw HTML omitted -->\n",
+            "<p>Possibly <!-- raw HTML omitted -->annoying<!-- raw HTML omitted -->.</p>\n"
+        ),
+        |opts| opts.safe = true,
+    );
+}
+
+#[test]
+fn sourcepos() {
+    html_opts(
+        concat!(
+            "# Hi\n",
+            "\n",
+            "Hello *world*.\n",
+            "\n",
+            "> Quote.\n",
+            "\n",
+            "-----\n",
+            "\n",
+            "| a | b |\n",
+            "| --- | --: |\n",
+            "| c | d |\n"
+        ),
+        concat!(
+            "<h1 data-sourcepos=\"1:1-1:4\">Hi</h1>\n",
+            "<p data-sourcepos=\"3:1-3:14\">Hello <em>world</em>.</p>\n",
+            "<blockquote data-sourcepos=\"5:1-5:8\">\n",
+            "<p data-sourcepos=\"5:3-5:8\">Quote.</p>\n",
+            "</blockquote>\n",
+            "<hr data-sourcepos=\"7:1-7:5\" />\n",
+            "<table data-sourcepos=\"9:1-11:9\">\n",
+            "<thead>\n",
+            "<tr data-sourcepos=\"9:1-9:9\">\n",
+            "<th data-sourcepos=\"9:2-9:4\">a</th>\n",
+            "<th align=\"right\" data-sourcepos=\"9:6-9:8\">b</th>\n",
+            "</tr>\n",
+            "</thead>\n",
+            "<tbody>\n",
+            "<tr data-sourcepos=\"11:1-11:9\">\n",
+            "<td data-sourcepos=\"11:2-11:4\">c</td>\n",
+            "<td align=\"right\" data-sourcepos=\"11:6-11:8\">d</td>\n",
+            "</tr></tbody></table>\n"
+        ),
+        |opts| {
+            opts.sourcepos = true;
+            opts.ext_table = true;
+        },
+    );
+}
+
+#[test]
+fn inline_sourcepos() {
+    let arena = Arena::new();
+    let options = ComrakOptions {
+        ext_table: true,
+        ext_autolink: true,
+        ext_strikethrough: true,
+        ..ComrakOptions::default()
+    };
+
+    let input = concat!(
+        "Hello *world* and **[link](/u)**\n",
+        "  next `code` ~~gone~~ line\n",
+        "\n",
+        "> | a | `b` |\n",
+        "> | - | - |\n",
+        "> | www.example.com | *c* |\n",
+        "\n",
+        "\\* &amp; www.example.com x\n"
+    );
+    let root = parse_document(&arena, input, &options);
+
+    let mut positions = vec![];
+    for node in root.descendants() {
+        let ast = node.data.borrow();
+        if !ast.value.block() {
+            positions.push(format!(
+                "{:?} {}:{}-{}:{}",
+                ast.value,
+                ast.start_line,
+                ast.start_column,
+                ast.end_line,
+                ast.end_column
+            ));
+        }
+    }
+
+    compare_strs(
+        &positions.join("\n"),
+        concat!(
+            "Text(\"Hello \") 1:1-1:6\n",
+            "Emph 1:7-1:13\n",
+            "Text(\"world\") 1:8-1:12\n",
+            "Text(\" and \") 1:14-1:18\n",
+            "Strong 1:19-1:32\n",
+            "Link(NodeLink { url: \"/u\", title: \"\" }) 1:21-1:30\n",
+            "Text(\"link\") 1:22-1:25\n",
+            "SoftBreak 1:33-1:33\n",
+            "Text(\"next \") 2:3-2:7\n",
+            "Code(\"code\") 2:8-2:13\n",
+            "Text(\" \") 2:14-2:14\n",
+            "Strikethrough 2:15-2:22\n",
+            "Text(\"gone\") 2:17-2:20\n",
+            "Text(\" line\") 2:23-2:27\n",
+            "Text(\"a\") 4:5-4:5\n",
+            "Code(\"b\") 4:9-4:11\n",
+            "Link(NodeLink { url: \"http://www.example.com\", title: \"\" }) 6:5-6:19\n",
+            "Text(\"www.example.com\") 6:5-6:19\n",
+            "Emph 6:23-6:25\n",
+            "Text(\"c\") 6:24-6:24\n",
+            "Text(\"* & \") 8:1-8:9\n",
+            "Link(NodeLink { url: \"http://www.example.com\", title: \"\" }) 8:10-8:24\n",
+            "Text(\"www.example.com\") 8:10-8:24\n",
+            "Text(\" x\") 8:25-8:26"
+        ),
+        "inline sourcepos",
+    );
+}
+
+#[test]
+fn format_to_writer() {
+    struct FailingWriter(usize);
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.0 < buf.len() {
+                return Err(io::Error::new(io::ErrorKind::Other, "full"));
+            }
+            self.0 -= buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let arena = Arena::new();
+    let options = ComrakOptions::default();
+    let root = parse_document(&arena, "# Hi\n\n- *a*\n- b\n", &options);
+
+    let mut output = vec![];
+    html::format_document_to(root, &options, &mut output).unwrap();
+    compare_strs(
+        &String::from_utf8(output).unwrap(),
+        &html::format_document(root, &options),
+        "html writer",
+    );
+
+    let mut output = vec![];
+    cm::format_document_to(root, &options, &mut output).unwrap();
+    compare_strs(
+        &String::from_utf8(output).unwrap(),
+        &cm::format_document(root, &options),
+        "commonmark writer",
+    );
+
+    let err = html::format_document_to(root, &options, &mut FailingWriter(10)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+    let err = cm::format_document_to(root, &options, &mut FailingWriter(10)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+
+    // CommonMark output is written out block by block, not all at once at the end.
+    let mut output = vec![];
+    {
+        let mut writes = ChunkWriter(&mut output);
+        cm::format_document_to(root, &options, &mut writes).unwrap();
+    }
+    assert_eq!(output.len(), 2);
+    compare_strs(&output.concat(), "# Hi\n\n  - *a*\n  - b\n", "commonmark chunks");
+
+    let options = ComrakOptions {
+        width: 12,
+        ..ComrakOptions::default()
+    };
+    let root = parse_document(&arena, "> one two three four\n\nfive six seven eight\n", &options);
+    let mut output = vec![];
+    {
+        let mut writes = ChunkWriter(&mut output);
+        cm::format_document_to(root, &options, &mut writes).unwrap();
+    }
+    assert_eq!(output.len(), 3);
+    compare_strs(
+        &output.concat(),
+        &cm::format_document(root, &options),
+        "commonmark chunks",
+    );
+    compare_strs(
+        &output.concat(),
+        "> one two\n> three four\n\nfive six\nseven eight\n",
+        "commonmark chunks",
+    );
+}
+
+struct ChunkWriter<'a>(&'a mut Vec<String>);
+
+impl<'a> Write for ChunkWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.push(String::from_utf8(buf.to_vec()).unwrap());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn streaming_parser() {
+    let input = concat!(
+        "---\r\n",
+        "title: Ünïcödé\r\n",
+        "---\r\n",
+        "\r\n",
+        "# Hello, 世界\r\n",
+        "\r\n",
+        "> Quote\r",
+        "lazy *line*\n",
+        "\n",
+        "- [a] `b`\n",
+        "\n",
+        "[a]: /url\n"
+    );
+
+    let options = ComrakOptions {
+        sourcepos: true,
+        front_matter_delimiter: Some("---".to_string()),
+        ..ComrakOptions::default()
+    };
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &options);
+    let expected = cm::format_document(root, &options) + &html::format_document(root, &options);
+    assert!(expected.starts_with("---\r\ntitle"));
+
+    for i in 0..input.len() + 1 {
+        for j in i..input.len() + 1 {
+            let mut parser = StreamingParser::new(&arena, &options);
+            parser.feed_bytes(&input.as_bytes()[..i]);
+            parser.feed_bytes(&input.as_bytes()[i..j]);
+            parser.feed_bytes(&input.as_bytes()[j..]);
+            let root = parser.finish();
+            let output = cm::format_document(root, &options) + &html::format_document(root, &options);
+            compare_strs(&output, &expected, &format!("streaming {} {}", i, j));
+        }
+    }
+
+    let mut parser = StreamingParser::new(&arena, &options);
+    parser.feed_bytes(b"a\xff\xe4\xb8");
+    parser.feed("b\n");
+    compare_strs(
+        &html::format_document(parser.finish(), &options),
+        "<p data-sourcepos=\"1:1-1:8\">a\u{fffd}\u{fffd}b</p>\n",
+        "invalid utf-8",
+    );
+}
+
+#[test]
+fn streaming_parser_front_matter_limit() {
+    let options = ComrakOptions {
+        front_matter_delimiter: Some("---".to_string()),
+        ..ComrakOptions::default()
+    };
+    let arena = Arena::new();
+    let limit = ::strings::MAX_FRONT_MATTER_LENGTH;
+
+    for &(closing, expected) in &[(limit - 1, true), (limit, false)] {
+        let input = format!("---\n{}\n---\n\nText.\n", "a".repeat(closing - 5));
+        let root = parse_document(&arena, &input, &options);
+        match root.first_child().unwrap().data.borrow().value {
+            NodeValue::FrontMatter(..) => assert!(expected, "closing delimiter at {}", closing),
+            ref value => assert!(!expected, "expected front matter, got {:?}", value),
+        }
+
+        let mut parser = StreamingParser::new(&arena, &options);
+        for chunk in input.as_bytes().chunks(1000) {
+            parser.feed_bytes(chunk);
+        }
+        compare_strs(
+            &html::format_document(parser.finish(), &options),
+            &html::format_document(root, &options),
+            &format!("streaming closing delimiter at {}", closing),
+        );
+    }
+
+    let mut parser = StreamingParser::new(&arena, &options);
+    parser.feed("---\n");
+    let line = "a".repeat(99) + "\n";
+    for _ in 0..limit / line.len() + 1 {
+        parser.feed(&line);
+    }
+    assert!(!format!("{:?}", parser).contains("line_number: 0,"));
+}
+
+#[test]
+fn streaming_parser_read_from() {
+    struct ByteAtATime<'a>(&'a [u8]);
+
+    impl<'a> io::Read for ByteAtATime<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::Other, "closed"));
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    let options = ComrakOptions::default();
+    let arena = Arena::new();
+
+    let mut parser = StreamingParser::new(&arena, &options);
+    parser.read_from("# 世界\n".as_bytes()).unwrap();
+    parser.read_from(&b"*\xc3\xbc*\n"[..]).unwrap();
+    compare_strs(
+        &html::format_document(parser.finish(), &options),
+        "<h1>世界</h1>\n<p><em>ü</em></p>\n",
+        "read_from",
+    );
+
+    let mut parser = StreamingParser::new(&arena, &options);
+    match parser.read_from(ByteAtATime("ab 世界".as_bytes())) {
+        Err(Error::Io(ref err)) => assert_eq!(err.kind(), io::ErrorKind::Other),
+        _ => panic!("expected an I/O error"),
+    }
+    compare_strs(
+        &html::format_document(parser.finish(), &options),
+        "<p>ab 世界</p>\n",
+        "read_from partial",
+    );
+
+    let mut parser = StreamingParser::new(&arena, &options);
+    match parser.read_from(&b"abc\xe4\xb8\x41"[..]) {
+        Err(Error::InvalidUtf8 { offset }) => assert_eq!(offset, 3),
+        _ => panic!("expected invalid UTF-8"),
+    }
+    compare_strs(
+        &html::format_document(parser.finish(), &options),
+        "<p>abc</p>\n",
+        "read_from invalid",
+    );
+
+    let mut parser = StreamingParser::new(&arena, &options);
+    match parser.read_from(&b"abcd\xe4\xb8"[..]) {
+        Err(Error::InvalidUtf8 { offset }) => assert_eq!(offset, 4),
+        _ => panic!("expected a truncated character"),
+    }
+    compare_strs(
+        &html::format_document(parser.finish(), &options),
+        "<p>abcd</p>\n",
+        "read_from truncated",
+    );
+}
+
+#[test]
+fn syntax_highlighter() {
+    struct Bracketed;
+
+    impl SyntaxHighlighter for Bracketed {
+        fn highlight(&self, info: &str, code: &str) -> Option<String> {
+            Some(format!("<pre>[{}] {}</pre>\n", info, code.trim()))
+        }
+    }
+
+    let arena = Arena::new();
+    let options = ComrakOptions::default();
+    let root = parse_document(
+        &arena,
+        "``` rust  main\nfn <main>\n```\n\n    indented\n\n~~~\n<x>\n~~~\n",
+        &options,
+    );
+
+    let mut output = vec![];
+    HtmlFormatter::new(&options, &mut output)
+        .with_highlighter(&Bracketed)
+        .format_document(root)
+        .unwrap();
+    compare_strs(
+        &String::from_utf8(output).unwrap(),
+        concat!(
+            "<pre>[rust  main] fn <main></pre>\n",
+            "<pre><code>indented\n",
+            "</code></pre>\n",
+            "<pre>[] <x></pre>\n"
+        ),
+        "highlighter",
+    );
+}
+
+#[test]
+fn html_renderer() {
+    struct LazyImages {
+        images: usize,
+    }
+
+    impl HtmlRenderer for LazyImages {
+        fn render_image<'a>(
+            &mut self,
+            f: &mut HtmlFormatter,
+            node: &'a AstNode<'a>,
+            nl: &NodeLink,
+            entering: bool,
+        ) -> io::Result<bool> {
+            if entering {
+                self.images += 1;
+                f.write_all(b"<img loading=\"lazy\" src=\"")?;
+                f.escape_href(&nl.url)?;
+                f.write_all(b"\" alt=\"")?;
+                Ok(true)
+            } else {
+                f.render_image(node, nl, entering)
+            }
+        }
+
+        fn render_text<'a>(
+            &mut self,
+            f: &mut HtmlFormatter,
+            node: &'a AstNode<'a>,
+            literal: &str,
+            entering: bool,
+        ) -> io::Result<bool> {
+            if entering {
+                f.escape(&literal.to_uppercase())?;
+                // Renderers may modify the nodes they are given.
+                node.data.borrow_mut().value = NodeValue::Text(literal.to_lowercase());
+            }
+            Ok(false)
+        }
+    }
+
+    struct Unchanged;
+
+    impl HtmlRenderer for Unchanged {}
+
+    let input = concat!(
+        "# Hi\n",
+        "\n",
+        "> ![a *b* & c](/img.png \"t\") and [x](/ü)\n",
+        "\n",
+        "1. `code`\n",
+        "2. <b>html</b>\n",
+        "\n",
+        "| a |\n",
+        "|---|\n",
+        "| b |\n"
+    );
+
+    let arena = Arena::new();
+    let options = ComrakOptions {
+        ext_table: true,
+        sourcepos: true,
+        ..ComrakOptions::default()
+    };
+    let root = parse_document(&arena, input, &options);
+
+    let mut output = vec![];
+    HtmlFormatter::new(&options, &mut output)
+        .with_renderer(&mut Unchanged)
+        .format_document(root)
+        .unwrap();
+    compare_strs(
+        &String::from_utf8(output).unwrap(),
+        &html::format_document(root, &options),
+        "default renderer",
+    );
+
+    let mut renderer = LazyImages { images: 0 };
+    let mut output = vec![];
+    HtmlFormatter::new(&options, &mut output)
+        .with_renderer(&mut renderer)
+        .format_document(root)
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(renderer.images, 1);
+    assert!(output.contains(concat!(
+        "<img loading=\"lazy\" src=\"/img.png\" alt=\"a b &amp; c\" title=\"t\" /> ",
+        "AND <a href=\"/%C3%BC\">X</a>"
+    )));
+}
+
+#[test]
+fn visitor() {
+    struct Transformer<'a> {
+        arena: &'a Arena<AstNode<'a>>,
+        events: Vec<String>,
+    }
+
+    impl<'a> Visitor<'a> for Transformer<'a> {
+        fn enter(&mut self, node: &'a AstNode<'a>) -> Visit<'a> {
+            self.events.push(format!("+{:?}", node.data.borrow().value));
+            match node.data.borrow().value {
+                NodeValue::BlockQuote => return Visit::SkipChildren,
+                NodeValue::Strong => return Visit::Unlink,
+                _ => (),
+            }
+            let code = match node.data.borrow().value {
+                NodeValue::Code(ref code) => code.clone(),
+                _ => return Visit::Continue,
+            };
+            let text = |s: &str| AstNode::new_in(self.arena, NodeValue::Text(s.to_string()));
+            Visit::Replace(vec![text("["), text(&code), text("]")])
+        }
+
+        fn exit(&mut self, node: &'a AstNode<'a>) -> Visit<'a> {
+            self.events.push(format!("-{:?}", node.data.borrow().value));
+            Visit::Continue
+        }
+    }
+
+    let arena = Arena::new();
+    let options = ComrakOptions::default();
+    let root = parse_document(&arena, "a `b` **c** d\n\n> e\n", &options);
+
+    let mut transformer = Transformer {
+        arena: &arena,
+        events: vec![],
+    };
+    walk(root, &mut transformer);
+
+    compare_strs(
+        &transformer.events.join(" "),
+        concat!(
+            "+Document +Paragraph +Text(\"a \") -Text(\"a \") +Code(\"b\") ",
+            "+Text(\" \") -Text(\" \") +Strong +Text(\" d\") -Text(\" d\") -Paragraph ",
+            "+BlockQuote -BlockQuote -Document"
+        ),
+        "visitor events",
+    );
+    compare_strs(
+        &html::format_document(root, &options),
+        "<p>a [b]  d</p>\n<blockquote>\n<p>e</p>\n</blockquote>\n",
+        "visitor output",
+    );
+}
+
+#[test]
+fn node_construction() {
+    let arena = Arena::new();
+    let options = ComrakOptions::default();
+    let node = |value| AstNode::new_in(&arena, value);
+    let text = |s: &str| node(NodeValue::Text(s.to_string()));
+
+    let root = node(NodeValue::Document);
+    let heading = node(NodeValue::Heading(NodeHeading {
+        level: 2,
+        setext: false,
+    }));
+    heading.append_checked(text("v1.0")).unwrap();
+    root.append_checked(heading).unwrap();
+
+    let nl = NodeList {
+        list_type: ListType::Bullet,
+        bullet_char: b'-',
+        tight: true,
+        ..NodeList::default()
+    };
+    let list = node(NodeValue::List(nl));
+    heading.insert_after_checked(list).unwrap();
+
+    for change in &["Fixed *bugs*", "Added `features`"] {
+        let item = node(NodeValue::Item(nl));
+        let paragraph = node(NodeValue::Paragraph);
+        let strong = node(NodeValue::Strong);
+        strong.append_checked(text(change)).unwrap();
+        paragraph.append_checked(strong).unwrap();
+        item.append_checked(paragraph).unwrap();
+        list.append_checked(item).unwrap();
+    }
+
+    let err = list.append_checked(node(NodeValue::Paragraph)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!("{:?} node cannot contain Paragraph node", NodeValue::List(nl))
+    );
+    assert!(heading.prepend_checked(node(NodeValue::ThematicBreak)).is_err());
+    assert!(
+        list.first_child()
+            .unwrap()
+            .insert_before_checked(text("loose"))
+            .is_err()
+    );
+    assert!(root.append_checked(node(NodeValue::Document)).is_err());
+    assert!(text("x").append_checked(text("y")).is_err());
+
+    let quote = node(NodeValue::BlockQuote);
+    assert!(root.append_checked(node(NodeValue::TableCell)).is_err());
+    assert!(root.append_checked(node(NodeValue::TableRow(false))).is_err());
+    assert!(quote.append_checked(node(NodeValue::FrontMatter("---\n".to_string()))).is_err());
+    assert!(quote.append_checked(node(NodeValue::TableCell)).is_err());
+    let front_matter = node(NodeValue::FrontMatter("---\n---\n".to_string()));
+    assert!(node(NodeValue::Document).append_checked(front_matter).is_ok());
+
+    let inner = node(NodeValue::BlockQuote);
+    quote.append_checked(inner).unwrap();
+    let err = inner.append_checked(quote).unwrap_err();
+    assert!(err.cycle);
+    assert_eq!(err.to_string(), "BlockQuote node cannot be inserted within itself");
+    assert!(quote.append_checked(quote).is_err());
+    assert!(inner.prepend_checked(inner).is_err());
+    assert!(inner.insert_after_checked(inner).is_err());
+    assert!(inner.insert_before_checked(quote).is_err());
+    assert_eq!(quote.descendants().count(), 2);
+
+    compare_strs(
+        &html::format_document(root, &options),
+        concat!(
+            "<h2>v1.0</h2>\n",
+            "<ul>\n",
+            "<li><strong>Fixed *bugs*</strong></li>\n",
+            "<li><strong>Added `features`</strong></li>\n",
+            "</ul>\n"
+        ),
+        "constructed html",
+    );
+    compare_strs(
+        &cm::format_document(root, &options),
+        concat!(
+            "## v1.0\n",
+            "\n",
+            "  - **Fixed \\*bugs\\***\n",
+            "  - **Added \\`features\\`**\n"
+        ),
+        "constructed commonmark",
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_round_trip() {
+    let input = concat!(
+        "---\n",
+        "title: x\n",
+        "---\n",
+        "# Hi *there*\n",
+        "\n",
+        "3) a\n",
+        "4) [b](/u \"t\")[^1]\n",
+        "\n",
+        "> ```rust\n",
+        "> code\n",
+        "> ```\n",
+        "\n",
+        "| a | b |\n",
+        "|:--|--:|\n",
+        "| ~~c~~ | ![d](/e) |\n",
+        "\n",
+        "<div>html</div>\n",
+        "\n",
+        "[^1]: Note `code`.\n"
+    );
+
+    let options = ComrakOptions {
+        ext_strikethrough: true,
+        ext_table: true,
+        ext_footnotes: true,
+        front_matter_delimiter: Some("---".to_string()),
+        ..ComrakOptions::default()
+    };
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &options);
+    let json = json::format_document(root);
+    let round_tripped = json::parse_document(&arena, &json).unwrap();
+
+    compare_strs(&json::format_document(round_tripped), &json, "json");
+    compare_strs(
+        &html::format_document(round_tripped, &options),
+        &html::format_document(root, &options),
+        "json html",
+    );
+    compare_strs(
+        &cm::format_document(round_tripped, &options),
+        &cm::format_document(root, &options),
+        "json commonmark",
+    );
+
+    assert!(json::parse_document(&arena, "{\"type\": \"Paragraph\"}").is_err());
+    assert!(json::parse_document(&arena, "{\"type\": \"Nonsense\"}").is_err());
+    assert!(
+        json::parse_document(
+            &arena,
+            "{\"type\": \"Document\", \"children\": [{\"type\": \"TableCell\"}]}",
+        ).is_err()
+    );
+    let err = json::parse_document(
+        &arena,
+        concat!(
+            "{\"type\": \"Document\", \"children\": [",
+            "{\"type\": \"Table\", \"value\": [\"None\"], \"children\": [",
+            "{\"type\": \"TableRow\", \"value\": true, \"children\": [",
+            "{\"type\": \"TableCell\"}, {\"type\": \"TableCell\"}]}]}]}"
+        ),
+    ).unwrap_err();
+    assert!(err.to_string().starts_with("table row has more cells"));
+    assert!(
+        json::parse_document(
+            &arena,
+            "{\"type\": \"Document\", \"children\": [{\"type\": \"Table\", \"value\": []}]}",
+        ).is_err()
+    );
+}
+
+#[test]
+fn xml_output() {
+    let input = concat!(
+        "- [x] Done & dusted\n",
+        "- Not ~~a task~~\n",
+        "\n",
+        "| a | b |\n",
+        "|:-:|---|\n",
+        "| `c` | [d](/e \"f\") |\n"
+    );
+
+    let options = ComrakOptions {
+        ext_strikethrough: true,
+        ext_table: true,
+        ext_tasklist: true,
+        sourcepos: true,
+        ..ComrakOptions::default()
+    };
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &options);
+    compare_strs(
+        &xml::format_document(root, &options),
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<!DOCTYPE document SYSTEM \"CommonMark.dtd\">\n",
+            "<document xmlns=\"http://commonmark.org/xml/1.0\">\n",
+            "  <list sourcepos=\"1:1-2:16\" type=\"bullet\" tight=\"true\">\n",
+            "    <tasklist sourcepos=\"1:1-1:19\" completed=\"true\">\n",
+            "      <paragraph sourcepos=\"1:3-1:19\">\n",
+            "        <text sourcepos=\"1:7-1:19\" xml:space=\"preserve\">Done &amp; dusted</text>\n",
+            "      </paragraph>\n",
+            "    </tasklist>\n",
+            "    <item sourcepos=\"2:1-2:16\">\n",
+            "      <paragraph sourcepos=\"2:3-2:16\">\n",
+            "        <text sourcepos=\"2:3-2:6\" xml:space=\"preserve\">Not </text>\n",
+            "        <strikethrough sourcepos=\"2:7-2:16\">\n",
+            "          <text sourcepos=\"2:9-2:14\" xml:space=\"preserve\">a task</text>\n",
+            "        </strikethrough>\n",
+            "      </paragraph>\n",
+            "    </item>\n",
+            "  </list>\n",
+            "  <table sourcepos=\"4:1-6:21\">\n",
+            "    <table_header sourcepos=\"4:1-4:9\">\n",
+            "      <table_cell sourcepos=\"4:2-4:4\" align=\"center\">\n",
+            "        <text sourcepos=\"4:3-4:3\" xml:space=\"preserve\">a</text>\n",
+            "      </table_cell>\n",
+            "      <table_cell sourcepos=\"4:6-4:8\">\n",
+            "        <text sourcepos=\"4:7-4:7\" xml:space=\"preserve\">b</text>\n",
+            "      </table_cell>\n",
+            "    </table_header>\n",
+            "    <table_row sourcepos=\"6:1-6:21\">\n",
+            "      <table_cell sourcepos=\"6:2-6:6\" align=\"center\">\n",
+            "        <code sourcepos=\"6:3-6:5\" xml:space=\"preserve\">c</code>\n",
+            "      </table_cell>\n",
+            "      <table_cell sourcepos=\"6:8-6:20\">\n",
+            "        <link sourcepos=\"6:9-6:19\" destination=\"/e\" title=\"f\">\n",
+            "          <text sourcepos=\"6:10-6:10\" xml:space=\"preserve\">d</text>\n",
+            "        </link>\n",
+            "      </table_cell>\n",
+            "    </table_row>\n",
+            "  </table>\n",
+            "</document>\n"
+        ),
+        "xml",
+    );
+}
+
+#[test]
+fn plaintext() {
+    let input = concat!(
+        "# Shopping *list*\n",
+        "\n",
+        "Buy these from [the shop](http://shop.example \"Shop\"), or from\n",
+        "<http://market.example>:\n",
+        "\n",
+        "- [x] Apples\n",
+        "- [ ] `Bread`, ~~cake~~\n",
+        "  1. White\n",
+        "  2. Brown\n",
+        "\n",
+        "> Eat **well**.[^1]\n",
+        "\n",
+        "```\n",
+        "code\n",
+        "```\n",
+        "\n",
+        "| a | b |\n",
+        "|---|---|\n",
+        "| c | d |\n",
+        "\n",
+        "[^1]: Or not.\n"
+    );
+
+    let mut options = ComrakOptions {
+        ext_strikethrough: true,
+        ext_table: true,
+        ext_tasklist: true,
+        ext_footnotes: true,
+        ..ComrakOptions::default()
+    };
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &options);
+    compare_strs(
+        &plaintext::format_document(root, &options),
+        concat!(
+            "Shopping list\n",
+            "\n",
+            "Buy these from the shop (http://shop.example), or from\n",
+            "http://market.example:\n",
+            "\n",
+            "- [x] Apples\n",
+            "- [ ] Bread, cake\n",
+            "  1. White\n",
+            "  2. Brown\n",
+            "\n",
+            "Eat well.[1]\n",
+            "\n",
+            "code\n",
+            "\n",
+            "a | b\n",
+            "c | d\n",
+            "\n",
+            "[1] Or not.\n"
+        ),
+        "plaintext",
+    );
+
+    options.width = 20;
+    let root = parse_document(&arena, "- Buy these from [the shop](/shop) today.\n", &options);
+    compare_strs(
+        &plaintext::format_document(root, &options),
+        "- Buy these from the\n  shop (/shop)\n  today.\n",
+        "plaintext wrapped",
+    );
+
+    options.hardbreaks = true;
+    let root = parse_document(&arena, "- Buy these\n  from the shop today.\n", &options);
+    compare_strs(
+        &plaintext::format_document(root, &options),
+        "- Buy these\n  from the shop today.\n",
+        "plaintext hardbreaks",
+    );
+}
+
+#[test]
+fn man_output() {
+    let input = concat!(
+        "# Name\n",
+        "\n",
+        "tool - frobnicate \\\\ *files*\n",
+        "\n",
+        "## Options\n",
+        "\n",
+        "* `--all`: **everything**\n",
+        "* see [docs](http://x.example/a-b)\n",
+        "\n",
+        "3. one\n",
+        "\n",
+        "> .quoted\n",
+        "\n",
+        "```\n",
+        ".not a request\n",
+        "```\n",
+        "\n",
+        "| a | b |\n",
+        "|---|--:|\n",
+        "| c | d |\n"
+    );
+
+    let mut options = ComrakOptions {
+        ext_table: true,
+        ..ComrakOptions::default()
+    };
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &options);
+    compare_strs(
+        &man::format_document(root, &options),
+        concat!(
+            ".SH\n",
+            "Name\n",
+            ".PP\n",
+            "tool \\- frobnicate \\e \\f[I]files\\f[]\n",
+            ".SS\n",
+            "Options\n",
+            ".IP \\[bu] 2\n",
+            "\\f[C]\\-\\-all\\f[]: \\f[B]everything\\f[]\n",
+            ".IP \\[bu] 2\n",
+            "see docs (http://x.example/a\\-b)\n",
+            ".IP \"3.\" 4\n",
+            "one\n",
+            ".RS\n",
+            ".PP\n",
+            "\\&.quoted\n",
+            ".RE\n",
+            ".IP\n",
+            ".nf\n",
+            "\\f[C]\n",
+            "\\&.not a request\n",
+            "\\f[]\n",
+            ".fi\n",
+            ".TS\n",
+            "l r.\n",
+            "a\tb\n",
+            "c\td\n",
+            ".TE\n"
+        ),
+        "man",
+    );
+
+    options.width = 20;
+    let root = parse_document(&arena, "Some words to wrap. .Here and there.\n", &options);
+    compare_strs(
+        &man::format_document(root, &options),
+        ".PP\nSome words to wrap.\n\\&.Here and there.\n",
+        "man wrapped",
+    );
+
+    options.width = 10;
+    let root = parse_document(&arena, "aaaaaaaa\n.bbbbbbbb\n", &options);
+    compare_strs(
+        &man::format_document(root, &options),
+        ".PP\naaaaaaaa\n\\&.bbbbbbbb\n",
+        "man wrapped after soft break",
+    );
+}
+
+#[test]
+fn latex_output() {
+    let input = concat!(
+        "## 100% *done* -- ~~nearly~~ x^2^\n",
+        "\n",
+        "- a_b {c}\n",
+        "- see <http://x.example/a_b#c>\n",
+        "\n",
+        "3) [three](http://x.example/~u)[^note]\n",
+        "   1. nested[^note]\n",
+        "\n",
+        "> `\\cmd` & ![img](a.png)\n",
+        "\n",
+        "```\n",
+        "\\verbatim{}\n",
+        "```\n",
+        "\n",
+        "| a | b | c |\n",
+        "|:--|:-:|--:|\n",
+        "| 1 | 2 | 3 |\n",
+        "\n",
+        "[^note]: A *note*.\n"
+    );
+
+    let options = ComrakOptions {
+        ext_strikethrough: true,
+        ext_superscript: true,
+        ext_autolink: true,
+        ext_table: true,
+        ext_footnotes: true,
+        ..ComrakOptions::default()
+    };
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &options);
+    compare_strs(
+        &latex::format_document(root, &options),
+        concat!(
+            "\\subsection{100\\% \\emph{done} -{}- \\sout{nearly} x\\textsuperscript{2}}\n",
+            "\n",
+            "\\begin{itemize}\n",
+            "\\item a\\_b \\{c\\}\n",
+            "\\item see \\url{http://x.example/a\\_b\\#c}\n",
+            "\\end{itemize}\n",
+            "\n",
+            "\\begin{enumerate}\n",
+            "\\def\\labelenumi{\\arabic{enumi})}\n",
+            "\\setcounter{enumi}{2}\n",
+            "\\item \\href{http://x.example/~u}{three}\\footnote{A \\emph{note}.}\n",
+            "\\begin{enumerate}\n",
+            "\\item nested\\footnotemark[1]\n",
+            "\\end{enumerate}\n",
+            "\n",
+            "\\end{enumerate}\n",
+            "\n",
+            "\\begin{quote}\n",
+            "\\texttt{\\textbackslash{}cmd} \\& \\protect\\includegraphics{a.png}\n",
+            "\n",
+            "\\end{quote}\n",
+            "\n",
+            "\\begin{verbatim}\n",
+            "\\verbatim{}\n",
+            "\\end{verbatim}\n",
+            "\n",
+            "\\begin{tabular}{lcr}\n",
+            "a & b & c \\\\\n",
+            "\\hline\n",
+            "1 & 2 & 3 \\\\\n",
+            "\\end{tabular}\n"
+        ),
+        "latex",
+    );
+
+    let root = parse_document(&arena, "0. zero\n", &options);
+    compare_strs(
+        &latex::format_document(root, &options),
+        "\\begin{enumerate}\n\\setcounter{enumi}{-1}\n\\item zero\n\\end{enumerate}\n",
+        "latex zero",
+    );
+}
@@ -0,0 +1,223 @@
+//! Table of contents generation.
+
+use arena_tree::Node;
+use html;
+use html::Anchorizer;
+use nodes::{make_block, AstNode, ListType, NodeLink, NodeList, NodeValue};
+use parser::ComrakOptions;
+use std::cell::RefCell;
+use typed_arena::Arena;
+
+/// The text which, alone in a paragraph, marks where `replace_toc_markers` inserts a table of
+/// contents.
+pub const TOC_MARKER: &str = "[[_TOC_]]";
+
+/// A single heading in a document's table of contents.
+#[derive(Debug, Clone)]
+pub struct TocEntry {
+    /// The level of the heading, from 1 to 6.
+    pub level: u32,
+
+    /// The plain text content of the heading.
+    pub text: String,
+
+    /// The anchor ID of the heading, as generated by the header IDs extension (without any
+    /// prefix).
+    pub slug: String,
+
+    /// The line in the input document the heading starts at.
+    pub line: u32,
+
+    /// The headings nested under this one.
+    pub children: Vec<TocEntry>,
+}
+
+/// Walks the document for headings, returning them as a tree nested by level.
+///
+/// A heading is nested under the closest preceding heading with a lower level; skipped levels
+/// don't introduce empty entries.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, ComrakOptions};
+/// # use comrak::toc::table_of_contents;
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let root = parse_document(
+///     &arena,
+///     "# Intro\n\n## Usage\n\n### CLI\n\n# Intro\n",
+///     &ComrakOptions::default());
+///
+/// let toc = table_of_contents(root);
+/// assert_eq!(toc.len(), 2);
+/// assert_eq!(toc[0].children[0].text, "Usage");
+/// assert_eq!(toc[0].children[0].children[0].slug, "cli");
+/// assert_eq!(toc[1].slug, "intro-1");
+/// assert_eq!(toc[1].line, 7);
+/// # }
+/// ```
+pub fn table_of_contents<'a>(root: &'a AstNode<'a>) -> Vec<TocEntry> {
+    let mut anchorizer = Anchorizer::new();
+    let mut entries: Vec<TocEntry> = vec![];
+
+    for node in root.descendants() {
+        let (level, line) = match node.data.borrow().value {
+            NodeValue::Heading(ref nh) => (nh.level, node.data.borrow().start_line),
+            _ => continue,
+        };
+
+        let mut text = String::new();
+        html::collect_text(node, &mut text);
+        let slug = anchorizer.anchorize(&text);
+
+        insert_entry(
+            &mut entries,
+            TocEntry {
+                level: level,
+                text: text,
+                slug: slug,
+                line: line,
+                children: vec![],
+            },
+        );
+    }
+
+    entries
+}
+
+fn insert_entry(entries: &mut Vec<TocEntry>, entry: TocEntry) {
+    let nest = match entries.last() {
+        Some(last) => last.level < entry.level,
+        None => false,
+    };
+
+    if nest {
+        insert_entry(&mut entries.last_mut().unwrap().children, entry);
+    } else {
+        entries.push(entry);
+    }
+}
+
+/// Builds a table of contents as a tight bullet list of links to each heading, ready to be
+/// inserted into a document.
+pub fn toc_to_ast<'a>(arena: &'a Arena<AstNode<'a>>, entries: &[TocEntry]) -> &'a AstNode<'a> {
+    let nl = NodeList {
+        list_type: ListType::Bullet,
+        bullet_char: b'-',
+        tight: true,
+        ..NodeList::default()
+    };
+
+    let line = entries.first().map_or(0, |e| e.line);
+    let list = make_node(arena, NodeValue::List(nl), line);
+
+    for entry in entries {
+        let item = make_node(arena, NodeValue::Item(nl), entry.line);
+        let paragraph = make_node(arena, NodeValue::Paragraph, entry.line);
+        let link = make_node(
+            arena,
+            NodeValue::Link(NodeLink {
+                url: format!("#{}", entry.slug),
+                title: String::new(),
+            }),
+            entry.line,
+        );
+        link.append(make_node(
+            arena,
+            NodeValue::Text(entry.text.clone()),
+            entry.line,
+        ));
+        paragraph.append(link);
+        item.append(paragraph);
+
+        if !entry.children.is_empty() {
+            item.append(toc_to_ast(arena, &entry.children));
+        }
+
+        list.append(item);
+    }
+
+    list
+}
+
+/// Formats a table of contents as a nested HTML list.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, ComrakOptions};
+/// # use comrak::toc::{table_of_contents, format_toc_html};
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "# Intro\n\n## Usage\n", &options);
+///
+/// assert_eq!(format_toc_html(&table_of_contents(root), &options),
+///            "<ul>\n<li><a href=\"#intro\">Intro</a>\n\
+///             <ul>\n<li><a href=\"#usage\">Usage</a></li>\n</ul>\n\
+///             </li>\n</ul>\n");
+/// # }
+/// ```
+pub fn format_toc_html(entries: &[TocEntry], options: &ComrakOptions) -> String {
+    if entries.is_empty() {
+        return String::new();
+    }
+
+    let arena = Arena::new();
+    html::format_document(toc_to_ast(&arena, entries), options)
+}
+
+/// Replaces each paragraph consisting only of `[[_TOC_]]` with the document's table of contents.
+/// Returns the number of markers replaced.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, format_html, ComrakOptions};
+/// # use comrak::toc::replace_toc_markers;
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "[[_TOC_]]\n\n# Intro\n", &options);
+///
+/// assert_eq!(replace_toc_markers(&arena, root), 1);
+/// assert_eq!(format_html(root, &options),
+///            "<ul>\n<li><a href=\"#intro\">Intro</a></li>\n</ul>\n<h1>Intro</h1>\n");
+/// # }
+/// ```
+pub fn replace_toc_markers<'a>(arena: &'a Arena<AstNode<'a>>, root: &'a AstNode<'a>) -> usize {
+    let markers = root.descendants()
+        .filter(|node| {
+            let ast = node.data.borrow();
+            match ast.value {
+                NodeValue::Paragraph => ast.content.trim() == TOC_MARKER,
+                _ => false,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if markers.is_empty() {
+        return 0;
+    }
+
+    let entries = table_of_contents(root);
+    for marker in &markers {
+        if !entries.is_empty() {
+            marker.insert_before(toc_to_ast(arena, &entries));
+        }
+        marker.detach();
+    }
+
+    markers.len()
+}
+
+fn make_node<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    value: NodeValue,
+    line: u32,
+) -> &'a AstNode<'a> {
+    let mut ast = make_block(value, line, 0);
+    ast.open = false;
+    arena.alloc(Node::new(RefCell::new(ast)))
+}
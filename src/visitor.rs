@@ -0,0 +1,218 @@
+//! Walking and transforming the AST.
+
+pub use arena_tree::NodeEdge;
+use nodes::{AstNode, NodeValue};
+
+/// What `walk` should do after a `Visitor` method returns.
+#[derive(Debug)]
+pub enum Visit<'a> {
+    /// Carry on walking the tree.
+    Continue,
+
+    /// Don't visit the node's descendants.  The node is still exited.  Has the same effect as
+    /// `Continue` when exiting a node.
+    SkipChildren,
+
+    /// Replace the node with the given nodes, which are not themselves visited.  When entering,
+    /// the node's descendants aren't visited and the node isn't exited.
+    Replace(Vec<&'a AstNode<'a>>),
+
+    /// Remove the node from the tree.  When entering, the node's descendants aren't visited and
+    /// the node isn't exited.
+    Unlink,
+}
+
+macro_rules! visitor {
+    ($($pat:pat => $enter:ident, $exit:ident, $enter_doc:expr, $exit_doc:expr;)*) => {
+        /// Callbacks for `walk`, called on entering and exiting each node.
+        ///
+        /// `enter` and `exit` call the method for the kind of node being visited, such as
+        /// `enter_link`; each of these returns `Visit::Continue` by default, so implementations
+        /// need only override the methods for the nodes they're interested in.
+        ///
+        /// No borrow of a node's data is held while it is visited, so it may be mutated freely.
+        pub trait Visitor<'a> {
+            /// Called before the node's descendants are visited.
+            fn enter(&mut self, node: &'a AstNode<'a>) -> Visit<'a> {
+                let f: fn(&mut Self, &'a AstNode<'a>) -> Visit<'a> =
+                    match node.data.borrow().value {
+                        $($pat => Self::$enter,)*
+                    };
+                f(self, node)
+            }
+
+            /// Called after the node's descendants are visited.
+            fn exit(&mut self, node: &'a AstNode<'a>) -> Visit<'a> {
+                let f: fn(&mut Self, &'a AstNode<'a>) -> Visit<'a> =
+                    match node.data.borrow().value {
+                        $($pat => Self::$exit,)*
+                    };
+                f(self, node)
+            }
+
+            $(
+                #[doc = $enter_doc]
+                fn $enter(&mut self, _node: &'a AstNode<'a>) -> Visit<'a> {
+                    Visit::Continue
+                }
+
+                #[doc = $exit_doc]
+                fn $exit(&mut self, _node: &'a AstNode<'a>) -> Visit<'a> {
+                    Visit::Continue
+                }
+            )*
+        }
+    }
+}
+
+visitor! {
+    NodeValue::Document => enter_document, exit_document,
+        "Called on entering the document root.", "Called on exiting the document root.";
+    NodeValue::FrontMatter(..) => enter_front_matter, exit_front_matter,
+        "Called on entering front matter.", "Called on exiting front matter.";
+    NodeValue::BlockQuote => enter_block_quote, exit_block_quote,
+        "Called on entering a block quote.", "Called on exiting a block quote.";
+    NodeValue::List(..) => enter_list, exit_list,
+        "Called on entering a list.", "Called on exiting a list.";
+    NodeValue::Item(..) => enter_item, exit_item,
+        "Called on entering a list item.", "Called on exiting a list item.";
+    NodeValue::CodeBlock(..) => enter_code_block, exit_code_block,
+        "Called on entering a code block.", "Called on exiting a code block.";
+    NodeValue::HtmlBlock(..) => enter_html_block, exit_html_block,
+        "Called on entering an HTML block.", "Called on exiting an HTML block.";
+    NodeValue::Paragraph => enter_paragraph, exit_paragraph,
+        "Called on entering a paragraph.", "Called on exiting a paragraph.";
+    NodeValue::Heading(..) => enter_heading, exit_heading,
+        "Called on entering a heading.", "Called on exiting a heading.";
+    NodeValue::ThematicBreak => enter_thematic_break, exit_thematic_break,
+        "Called on entering a thematic break.", "Called on exiting a thematic break.";
+    NodeValue::Table(..) => enter_table, exit_table,
+        "Called on entering a table.", "Called on exiting a table.";
+    NodeValue::TableRow(..) => enter_table_row, exit_table_row,
+        "Called on entering a table row.", "Called on exiting a table row.";
+    NodeValue::TableCell => enter_table_cell, exit_table_cell,
+        "Called on entering a table cell.", "Called on exiting a table cell.";
+    NodeValue::FootnoteDefinition(..) => enter_footnote_definition, exit_footnote_definition,
+        "Called on entering a footnote definition.", "Called on exiting a footnote definition.";
+    NodeValue::DescriptionList(..) => enter_description_list, exit_description_list,
+        "Called on entering a description list.", "Called on exiting a description list.";
+    NodeValue::DescriptionItem => enter_description_item, exit_description_item,
+        "Called on entering a description list item.",
+        "Called on exiting a description list item.";
+    NodeValue::DescriptionTerm => enter_description_term, exit_description_term,
+        "Called on entering a description term.", "Called on exiting a description term.";
+    NodeValue::DescriptionDetails(..) => enter_description_details, exit_description_details,
+        "Called on entering description details.", "Called on exiting description details.";
+    NodeValue::MathBlock(..) => enter_math_block, exit_math_block,
+        "Called on entering a math block.", "Called on exiting a math block.";
+    NodeValue::Text(..) => enter_text, exit_text,
+        "Called on entering text.", "Called on exiting text.";
+    NodeValue::SoftBreak => enter_soft_break, exit_soft_break,
+        "Called on entering a soft line break.", "Called on exiting a soft line break.";
+    NodeValue::LineBreak => enter_line_break, exit_line_break,
+        "Called on entering a hard line break.", "Called on exiting a hard line break.";
+    NodeValue::Code(..) => enter_code, exit_code,
+        "Called on entering an inline code span.", "Called on exiting an inline code span.";
+    NodeValue::HtmlInline(..) => enter_html_inline, exit_html_inline,
+        "Called on entering inline HTML.", "Called on exiting inline HTML.";
+    NodeValue::Emph => enter_emph, exit_emph,
+        "Called on entering emphasis.", "Called on exiting emphasis.";
+    NodeValue::Strong => enter_strong, exit_strong,
+        "Called on entering strong emphasis.", "Called on exiting strong emphasis.";
+    NodeValue::Strikethrough => enter_strikethrough, exit_strikethrough,
+        "Called on entering strikethrough.", "Called on exiting strikethrough.";
+    NodeValue::Superscript => enter_superscript, exit_superscript,
+        "Called on entering superscript.", "Called on exiting superscript.";
+    NodeValue::Subscript => enter_subscript, exit_subscript,
+        "Called on entering subscript.", "Called on exiting subscript.";
+    NodeValue::Link(..) => enter_link, exit_link,
+        "Called on entering a link.", "Called on exiting a link.";
+    NodeValue::Image(..) => enter_image, exit_image,
+        "Called on entering an image.", "Called on exiting an image.";
+    NodeValue::FootnoteReference(..) => enter_footnote_reference, exit_footnote_reference,
+        "Called on entering a footnote reference.", "Called on exiting a footnote reference.";
+    NodeValue::Math(..) => enter_math, exit_math,
+        "Called on entering a math span.", "Called on exiting a math span.";
+    NodeValue::TaskItem(..) => enter_task_item, exit_task_item,
+        "Called on entering a task list item's checkbox.",
+        "Called on exiting a task list item's checkbox.";
+}
+
+/// Walks the tree rooted at `root` in document order, calling the visitor on entering and
+/// exiting each node, and applying any changes it asks for.
+///
+/// ```
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # use comrak::{parse_document, format_html, ComrakOptions};
+/// # use comrak::nodes::{AstNode, NodeValue};
+/// # use comrak::visitor::{walk, Visit, Visitor};
+/// struct Cleanup;
+///
+/// impl<'a> Visitor<'a> for Cleanup {
+///     fn enter_text(&mut self, node: &'a AstNode<'a>) -> Visit<'a> {
+///         if let NodeValue::Text(ref mut text) = node.data.borrow_mut().value {
+///             *text = text.replace("my", "your");
+///         }
+///         Visit::Continue
+///     }
+///
+///     fn enter_html_inline(&mut self, _node: &'a AstNode<'a>) -> Visit<'a> {
+///         Visit::Unlink
+///     }
+///
+///     fn exit_emph(&mut self, node: &'a AstNode<'a>) -> Visit<'a> {
+///         Visit::Replace(node.children().collect())
+///     }
+/// }
+///
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "This is <b>my</b> *my input*.\n", &options);
+///
+/// walk(root, &mut Cleanup);
+/// assert_eq!(format_html(root, &options), "<p>This is your your input.</p>\n");
+/// # }
+/// ```
+pub fn walk<'a, V: Visitor<'a>>(root: &'a AstNode<'a>, visitor: &mut V) {
+    let mut traverse = root.traverse();
+    let mut pending = None;
+
+    while let Some(edge) = traverse.next() {
+        match edge {
+            NodeEdge::Start(node) => {
+                match visitor.enter(node) {
+                    Visit::Continue => (),
+                    Visit::SkipChildren => traverse.skip_children(),
+                    visit => {
+                        // The node is changed only once its end edge has been reached, at which
+                        // point the traversal has already moved past it.
+                        traverse.skip_children();
+                        pending = Some(visit);
+                    }
+                }
+            }
+            NodeEdge::End(node) => {
+                let visit = match pending.take() {
+                    Some(visit) => visit,
+                    None => visitor.exit(node),
+                };
+                apply(node, visit);
+            }
+        }
+    }
+}
+
+fn apply<'a>(node: &'a AstNode<'a>, visit: Visit<'a>) {
+    match visit {
+        Visit::Continue | Visit::SkipChildren => (),
+        Visit::Replace(replacements) => {
+            for replacement in replacements {
+                node.insert_before(replacement);
+            }
+            node.detach();
+        }
+        Visit::Unlink => node.detach(),
+    }
+}
@@ -0,0 +1,212 @@
+use ctype::isdigit;
+use std::cmp::{max, min};
+use std::io::{self, Write};
+use std::str;
+
+/// The kind of text a `LineWriter` produces, which decides how its lines may be broken.
+#[derive(PartialEq, Clone, Copy)]
+pub enum Syntax {
+    /// Markdown, to be read back as such: blank lines are prefixed too, and lines are never broken
+    /// before a digit, where they could begin an ordered list.
+    Markdown,
+
+    /// roff, where a line beginning with `.` or `'` is a request: a line broken before one of
+    /// those has it escaped.
+    Roff,
+
+    /// Anything else, such as plain text or LaTeX.
+    Text,
+}
+
+/// The output buffer of a line-based formatter, which prefixes each line (with block quote
+/// markers, list item indentation and so on) and wraps lines at a given width.
+pub struct LineWriter {
+    pub v: Vec<u8>,
+    pub prefix: Vec<u8>,
+    pub column: usize,
+    pub need_cr: u8,
+    pub last_breakable: usize,
+    pub begin_line: bool,
+    pub begin_content: bool,
+    pub in_tight_list_item: bool,
+    width: usize,
+    syntax: Syntax,
+}
+
+impl LineWriter {
+    /// Creates a writer of the given syntax, wrapping at `width` columns, or not at all if it is
+    /// zero.
+    pub fn new(width: usize, syntax: Syntax) -> Self {
+        LineWriter {
+            v: vec![],
+            prefix: vec![],
+            column: 0,
+            need_cr: 0,
+            last_breakable: 0,
+            begin_line: true,
+            begin_content: true,
+            in_tight_list_item: false,
+            width: width,
+            syntax: syntax,
+        }
+    }
+
+    pub fn cr(&mut self) {
+        self.need_cr = max(self.need_cr, 1);
+    }
+
+    pub fn blankline(&mut self) {
+        self.need_cr = max(self.need_cr, 2);
+    }
+
+    /// Writes a single byte of a character, counting columns in characters rather than bytes.
+    pub fn push(&mut self, c: u8) {
+        self.v.push(c);
+        if c & 0xc0 != 0x80 {
+            self.column += 1;
+        }
+    }
+
+    /// Writes `s`, counting columns in characters.
+    pub fn push_str(&mut self, s: &str) {
+        for &c in s.as_bytes() {
+            self.push(c);
+        }
+    }
+
+    /// Outputs `buf`, first ending the current line or leaving a blank line if asked to.  Spaces
+    /// and newlines are handled here, breaking lines at spaces if `wrap` is set.  Anything else
+    /// is passed to `outc` as the rest of `buf`, to write its first character with any escaping
+    /// and return how many bytes it used.
+    pub fn output<F>(&mut self, buf: &[u8], wrap: bool, mut outc: F)
+    where
+        F: FnMut(&mut LineWriter, &[u8]) -> usize,
+    {
+        if self.in_tight_list_item && self.need_cr > 1 {
+            self.need_cr = 1;
+        }
+
+        let mut k = self.v.len() as i32 - 1;
+        while self.need_cr > 0 {
+            if k < 0 || self.v[k as usize] == b'\n' {
+                k -= 1;
+            } else {
+                self.v.push(b'\n');
+                if self.syntax == Syntax::Markdown && self.need_cr > 1 {
+                    self.v.extend(&self.prefix);
+                }
+            }
+            self.column = 0;
+            self.begin_line = true;
+            self.begin_content = true;
+            self.need_cr -= 1;
+        }
+
+        let mut i = 0;
+        while i < buf.len() {
+            if self.begin_line {
+                self.v.extend(&self.prefix);
+                self.column = self.prefix.len();
+            }
+
+            let mut used = 1;
+            if buf[i] == b' ' && wrap {
+                if !self.begin_line {
+                    let last_nonspace = self.v.len();
+                    self.v.push(b' ');
+                    self.column += 1;
+                    self.begin_line = false;
+                    self.begin_content = false;
+                    while buf.get(i + 1) == Some(&(b' ')) {
+                        i += 1;
+                    }
+                    if !(self.syntax == Syntax::Markdown &&
+                             buf.get(i + 1).map_or(false, |&c| isdigit(c)))
+                    {
+                        self.last_breakable = last_nonspace;
+                    }
+                }
+            } else if buf[i] == b'\n' {
+                self.v.push(b'\n');
+                self.column = 0;
+                self.begin_line = true;
+                self.begin_content = true;
+                self.last_breakable = 0;
+            } else {
+                used = max(outc(self, &buf[i..]), 1);
+                self.begin_line = false;
+                self.begin_content = self.begin_content && isdigit(buf[i]);
+            }
+
+            if self.width > 0 && self.column > self.width && !self.begin_line &&
+                self.last_breakable > 0
+            {
+                let remainder = self.v[self.last_breakable + 1..].to_vec();
+                self.v.truncate(self.last_breakable);
+                self.v.push(b'\n');
+                self.v.extend(&self.prefix);
+                self.column = self.prefix.len();
+                if self.syntax == Syntax::Roff &&
+                    remainder.first().map_or(false, |&c| c == b'.' || c == b'\'')
+                {
+                    self.v.extend(b"\\&");
+                    self.column += 2;
+                }
+                self.v.extend(&remainder);
+                self.column += remainder.iter().filter(|&&c| c & 0xc0 != 0x80).count();
+                self.last_breakable = 0;
+                self.begin_line = false;
+                self.begin_content = false;
+            }
+
+            i += used;
+        }
+    }
+
+    /// Writes every completed line to `output` and drops it from the buffer.  The last two bytes
+    /// are always kept back, since `output` looks at them to decide how many newlines it still
+    /// needs, as is everything from a pending line break point on.
+    pub fn flush_lines(&mut self, output: &mut Write) -> io::Result<()> {
+        let line_start = self.v.iter().rposition(|&c| c == b'\n').map_or(0, |i| i + 1);
+        let mut n = min(line_start, self.v.len().saturating_sub(2));
+        if self.last_breakable > 0 {
+            n = min(n, self.last_breakable - 1);
+        }
+        if n == 0 {
+            return Ok(());
+        }
+
+        output.write_all(&self.v[..n])?;
+        self.v.drain(..n);
+        if self.last_breakable > 0 {
+            self.last_breakable -= n;
+        }
+        Ok(())
+    }
+
+    /// Ends the last line and writes out everything remaining.
+    pub fn finish(&mut self, output: &mut Write) -> io::Result<()> {
+        if !self.v.is_empty() && self.v[self.v.len() - 1] != b'\n' {
+            self.v.push(b'\n');
+        }
+        output.write_all(&self.v)?;
+        self.v.clear();
+        Ok(())
+    }
+}
+
+/// Decodes the character `buf` starts with, returning it with its length in bytes.  Anything
+/// that isn't valid UTF-8 is taken a byte at a time, as U+FFFD.
+pub fn first_char(buf: &[u8]) -> (char, usize) {
+    let len = match buf[0] {
+        c if c < 0x80 => 1,
+        c if c >= 0xf0 => 4,
+        c if c >= 0xe0 => 3,
+        _ => 2,
+    };
+    let len = min(len, buf.len());
+    match str::from_utf8(&buf[..len]).ok().and_then(|s| s.chars().next()) {
+        Some(c) => (c, len),
+        None => ('\u{fffd}', 1),
+    }
+}
@@ -0,0 +1,270 @@
+use nodes::{AstNode, ListDelimType, ListType, NodeValue, TableAlignment};
+use parser::ComrakOptions;
+use std::io::{self, Write};
+
+/// Formats an AST as CommonMark XML, as described by
+/// [CommonMark.dtd](https://github.com/commonmark/commonmark-spec/blob/master/CommonMark.dtd),
+/// modified by the given options.
+///
+/// ```
+/// # use comrak::{parse_document, format_xml, ComrakOptions};
+/// # extern crate comrak;
+/// # extern crate typed_arena;
+/// # fn main() {
+/// let arena = typed_arena::Arena::new();
+/// let options = ComrakOptions::default();
+/// let root = parse_document(&arena, "Hello, *world*!\n", &options);
+///
+/// assert_eq!(format_xml(root, &options),
+///            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+///             <!DOCTYPE document SYSTEM \"CommonMark.dtd\">\n\
+///             <document xmlns=\"http://commonmark.org/xml/1.0\">\n  \
+///               <paragraph>\n    \
+///                 <text xml:space=\"preserve\">Hello, </text>\n    \
+///                 <emph>\n      \
+///                   <text xml:space=\"preserve\">world</text>\n    \
+///                 </emph>\n    \
+///                 <text xml:space=\"preserve\">!</text>\n  \
+///               </paragraph>\n\
+///             </document>\n");
+/// # }
+/// ```
+pub fn format_document<'a>(root: &'a AstNode<'a>, options: &ComrakOptions) -> String {
+    let mut output = vec![];
+    format_document_to(root, options, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+/// Formats an AST as CommonMark XML, modified by the given options, writing the output to
+/// `output` as it is produced.
+pub fn format_document_to<'a>(
+    root: &'a AstNode<'a>,
+    options: &ComrakOptions,
+    output: &mut Write,
+) -> io::Result<()> {
+    output.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n")?;
+    output.write_all(b"<!DOCTYPE document SYSTEM \"CommonMark.dtd\">\n")?;
+
+    let mut f = XmlFormatter {
+        output: output,
+        options: options,
+        indent: 0,
+    };
+    f.format(root)
+}
+
+struct XmlFormatter<'o, 'w> {
+    output: &'w mut Write,
+    options: &'o ComrakOptions,
+    indent: usize,
+}
+
+impl<'o, 'w> XmlFormatter<'o, 'w> {
+    fn escape(&mut self, buffer: &str) -> io::Result<()> {
+        let src = buffer.as_bytes();
+        let mut org = 0;
+
+        for (i, &c) in src.iter().enumerate() {
+            let escaped: &[u8] = match c {
+                b'"' => b"&quot;",
+                b'&' => b"&amp;",
+                b'<' => b"&lt;",
+                b'>' => b"&gt;",
+                _ => continue,
+            };
+            self.output.write_all(&src[org..i])?;
+            self.output.write_all(escaped)?;
+            org = i + 1;
+        }
+
+        self.output.write_all(&src[org..])
+    }
+
+    fn write_indent(&mut self) -> io::Result<()> {
+        for _ in 0..self.indent {
+            self.output.write_all(b" ")?;
+        }
+        Ok(())
+    }
+
+    fn format<'a>(&mut self, node: &'a AstNode<'a>) -> io::Result<()> {
+        let ast = node.data.borrow();
+        match ast.value {
+            // A task item's checkbox is represented by the `tasklist` element containing it.
+            NodeValue::FrontMatter(..) |
+            NodeValue::TaskItem(..) => return Ok(()),
+            _ => (),
+        }
+
+        let name = node_name(node);
+
+        self.write_indent()?;
+        write!(self.output, "<{}", name)?;
+
+        if self.options.sourcepos && ast.start_line != 0 {
+            write!(
+                self.output,
+                " sourcepos=\"{}:{}-{}:{}\"",
+                ast.start_line,
+                ast.start_column,
+                ast.end_line,
+                ast.end_column
+            )?;
+        }
+
+        let mut literal = None;
+
+        match ast.value {
+            NodeValue::Document => {
+                self.output.write_all(b" xmlns=\"http://commonmark.org/xml/1.0\"")?
+            }
+            NodeValue::Text(ref text) |
+            NodeValue::Code(ref text) |
+            NodeValue::HtmlInline(ref text) => literal = Some(text),
+            NodeValue::HtmlBlock(ref nhb) => literal = Some(&nhb.literal),
+            NodeValue::CodeBlock(ref ncb) |
+            NodeValue::MathBlock(ref ncb) => {
+                if !ncb.info.is_empty() {
+                    self.output.write_all(b" info=\"")?;
+                    self.escape(&ncb.info)?;
+                    self.output.write_all(b"\"")?;
+                }
+                literal = Some(&ncb.literal);
+            }
+            NodeValue::List(ref nl) => {
+                if nl.list_type == ListType::Bullet {
+                    self.output.write_all(b" type=\"bullet\"")?;
+                } else {
+                    write!(self.output, " type=\"ordered\" start=\"{}\"", nl.start)?;
+                    match nl.delimiter {
+                        ListDelimType::Period => self.output.write_all(b" delim=\"period\"")?,
+                        ListDelimType::Paren => self.output.write_all(b" delim=\"paren\"")?,
+                    }
+                }
+                write!(self.output, " tight=\"{}\"", nl.tight)?;
+            }
+            NodeValue::DescriptionList(ref ndl) => {
+                write!(self.output, " tight=\"{}\"", ndl.tight)?;
+            }
+            NodeValue::Math(ref nm) => {
+                write!(self.output, " display=\"{}\"", nm.display_math)?;
+                literal = Some(&nm.literal);
+            }
+            NodeValue::Item(..) => {
+                if let Some(checked) = task_item_checked(node) {
+                    write!(self.output, " completed=\"{}\"", checked)?;
+                }
+            }
+            NodeValue::Heading(ref nch) => write!(self.output, " level=\"{}\"", nch.level)?,
+            NodeValue::TableCell => {
+                let column = node.preceding_siblings().count() - 1;
+                let table = node.parent().and_then(|row| row.parent()).unwrap();
+                let alignment = match table.data.borrow().value {
+                    NodeValue::Table(ref alignments) => alignments[column],
+                    _ => TableAlignment::None,
+                };
+                match alignment {
+                    TableAlignment::Left => self.output.write_all(b" align=\"left\"")?,
+                    TableAlignment::Right => self.output.write_all(b" align=\"right\"")?,
+                    TableAlignment::Center => self.output.write_all(b" align=\"center\"")?,
+                    TableAlignment::None => (),
+                }
+            }
+            NodeValue::FootnoteDefinition(ref label) |
+            NodeValue::FootnoteReference(ref label) => {
+                self.output.write_all(b" label=\"")?;
+                self.escape(label)?;
+                self.output.write_all(b"\"")?;
+            }
+            NodeValue::Link(ref nl) |
+            NodeValue::Image(ref nl) => {
+                self.output.write_all(b" destination=\"")?;
+                self.escape(&nl.url)?;
+                self.output.write_all(b"\" title=\"")?;
+                self.escape(&nl.title)?;
+                self.output.write_all(b"\"")?;
+            }
+            _ => (),
+        }
+
+        if let Some(literal) = literal {
+            self.output.write_all(b" xml:space=\"preserve\">")?;
+            self.escape(literal)?;
+            return writeln!(self.output, "</{}>", name);
+        }
+
+        if node.first_child().is_none() {
+            return self.output.write_all(b" />\n");
+        }
+
+        self.output.write_all(b">\n")?;
+        self.indent += 2;
+        for child in node.children() {
+            self.format(child)?;
+        }
+        self.indent -= 2;
+
+        self.write_indent()?;
+        writeln!(self.output, "</{}>", name)
+    }
+}
+
+fn node_name<'a>(node: &'a AstNode<'a>) -> &'static str {
+    match node.data.borrow().value {
+        NodeValue::Document => "document",
+        NodeValue::FrontMatter(..) => "front_matter",
+        NodeValue::BlockQuote => "block_quote",
+        NodeValue::List(..) => "list",
+        NodeValue::Item(..) => {
+            if task_item_checked(node).is_some() {
+                "tasklist"
+            } else {
+                "item"
+            }
+        }
+        NodeValue::CodeBlock(..) => "code_block",
+        NodeValue::HtmlBlock(..) => "html_block",
+        NodeValue::Paragraph => "paragraph",
+        NodeValue::Heading(..) => "heading",
+        NodeValue::ThematicBreak => "thematic_break",
+        NodeValue::Table(..) => "table",
+        NodeValue::TableRow(true) => "table_header",
+        NodeValue::TableRow(false) => "table_row",
+        NodeValue::TableCell => "table_cell",
+        NodeValue::FootnoteDefinition(..) => "footnote_definition",
+        NodeValue::DescriptionList(..) => "description_list",
+        NodeValue::DescriptionItem => "description_item",
+        NodeValue::DescriptionTerm => "description_term",
+        NodeValue::DescriptionDetails(..) => "description_details",
+        NodeValue::MathBlock(..) => "math_block",
+        NodeValue::Text(..) => "text",
+        NodeValue::SoftBreak => "softbreak",
+        NodeValue::LineBreak => "linebreak",
+        NodeValue::Code(..) => "code",
+        NodeValue::HtmlInline(..) => "html_inline",
+        NodeValue::Emph => "emph",
+        NodeValue::Strong => "strong",
+        NodeValue::Strikethrough => "strikethrough",
+        NodeValue::Superscript => "superscript",
+        NodeValue::Subscript => "subscript",
+        NodeValue::Link(..) => "link",
+        NodeValue::Image(..) => "image",
+        NodeValue::FootnoteReference(..) => "footnote_reference",
+        NodeValue::Math(..) => "math",
+        NodeValue::TaskItem(..) => "task_item",
+    }
+}
+
+/// Returns whether the given item is a checked or unchecked task list item, or `None` if it isn't
+/// one.
+fn task_item_checked<'a>(item: &'a AstNode<'a>) -> Option<bool> {
+    let marker = match item.first_child().and_then(|p| p.first_child()) {
+        Some(marker) => marker,
+        None => return None,
+    };
+
+    match marker.data.borrow().value {
+        NodeValue::TaskItem(checked) => Some(checked),
+        _ => None,
+    }
+}